@@ -20,3 +20,25 @@ fn bench_cache(b: &mut Bencher) {
     let sql = "SELECT 1, 'test', 3.14 UNION SELECT 2, 'exp', 2.71";
     b.iter(|| db.prepare_cached(sql).unwrap());
 }
+
+const COUNT_SQL: &str = "WITH RECURSIVE cnt(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM cnt \
+                         LIMIT 100000) SELECT x FROM cnt";
+
+#[bench]
+fn bench_query_map(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    let mut stmt = db.prepare(COUNT_SQL).unwrap();
+    b.iter(|| {
+        stmt.query_map(&[], |r| r.get::<_, i64>(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<i64>>>()
+            .unwrap()
+    });
+}
+
+#[bench]
+fn bench_query_map_into(b: &mut Bencher) {
+    let db = Connection::open_in_memory().unwrap();
+    let mut stmt = db.prepare(COUNT_SQL).unwrap();
+    b.iter(|| stmt.query_map_into(&[], |r| r.get_checked::<_, i64>(0)).unwrap());
+}
@@ -0,0 +1,216 @@
+//! Busy timeouts and busy handlers
+use std::mem;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::time::Duration;
+
+use ffi;
+
+use {Connection, InnerConnection, Result};
+
+/// The type of closure stored by `Connection::busy_handler`.
+pub type BusyHandler = Box<FnMut(i32) -> bool + Send>;
+
+impl Connection {
+    /// Set a busy handler that sleeps for up to `timeout` while a table is locked, retrying
+    /// the operation until it succeeds or the timeout expires. A zero `timeout` turns off all
+    /// busy handlers, so locked operations fail immediately with `SQLITE_BUSY`.
+    ///
+    /// Connections are opened with a 5 second busy timeout. Setting a timeout replaces any
+    /// handler set with `busy_handler`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn busy_timeout(&self, timeout: Duration) -> Result<()> {
+        let ms = timeout.as_secs()
+            .saturating_mul(1000)
+            .saturating_add((timeout.subsec_nanos() / 1_000_000) as u64);
+        let ms = if ms > c_int::max_value() as u64 {
+            c_int::max_value()
+        } else {
+            ms as c_int
+        };
+        self.db.borrow_mut().busy_timeout(ms)
+    }
+
+    /// Register a callback to be invoked when an operation cannot proceed because a table is
+    /// locked, replacing any busy timeout or previous busy handler. Passing `None` removes the
+    /// current handler, so locked operations fail immediately with `SQLITE_BUSY`.
+    ///
+    /// The callback receives the number of times it has already been invoked for the current
+    /// locking event. If it returns `true`, SQLite retries the operation (and calls the
+    /// handler again if the table is still locked); if it returns `false`, the operation fails
+    /// with `SQLITE_BUSY`. The callback is kept alive by the connection until it is replaced
+    /// or the connection is closed.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use std::thread;
+    /// # use std::time::Duration;
+    /// fn retry_a_few_times(conn: &Connection) -> Result<()> {
+    ///     conn.busy_handler(Some(|count| {
+    ///         thread::sleep(Duration::from_millis(10));
+    ///         count < 10
+    ///     }))
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn busy_handler<F>(&self, callback: Option<F>) -> Result<()>
+        where F: FnMut(i32) -> bool + Send + 'static
+    {
+        self.db.borrow_mut().busy_handler(callback.map(|f| Box::new(f) as BusyHandler))
+    }
+
+    /// Make `execute`, `execute_named`, `execute_returning`, `query_row`, `query_row_named` and
+    /// `query_row_and_then` retry statements that fail with `SQLITE_BUSY` or `SQLITE_LOCKED`,
+    /// making up to `max_attempts` attempts in total and sleeping for `backoff` between them. A
+    /// `max_attempts` of 0 or 1 turns retrying off, which is the default.
+    ///
+    /// Retrying happens on top of the busy timeout or busy handler, so each attempt may itself
+    /// wait before failing; use `busy_timeout(Duration::from_secs(0))` to rely on retrying
+    /// alone. Nothing is retried while a transaction is open, since the transaction may need to
+    /// be rolled back to resolve the contention (and retrying a statement would not undo the
+    /// earlier statements of the transaction). Methods of `Statement`, `execute_batch` and
+    /// queries that return rows incrementally are never retried.
+    pub fn set_auto_retry(&self, max_attempts: u32, backoff: Duration) {
+        self.db.borrow_mut().auto_retry = if max_attempts > 1 {
+            Some((max_attempts, backoff))
+        } else {
+            None
+        };
+    }
+}
+
+impl InnerConnection {
+    fn busy_timeout(&mut self, ms: c_int) -> Result<()> {
+        let r = unsafe { ffi::sqlite3_busy_timeout(self.db(), ms) };
+        try!(self.decode_result(r));
+        // SQLite has dropped its reference to any previous handler.
+        self.busy_handler = None;
+        Ok(())
+    }
+
+    fn busy_handler(&mut self, callback: Option<BusyHandler>) -> Result<()> {
+        unsafe extern "C" fn call_boxed_handler(p_arg: *mut c_void, count: c_int) -> c_int {
+            let handler: *mut BusyHandler = mem::transmute(p_arg);
+            if (*handler)(count) { 1 } else { 0 }
+        }
+
+        let mut callback = callback.map(Box::new);
+        let r = unsafe {
+            match callback {
+                Some(ref mut handler) => {
+                    let p_arg: *mut BusyHandler = &mut **handler;
+                    ffi::sqlite3_busy_handler(self.db(),
+                                              Some(call_boxed_handler),
+                                              mem::transmute(p_arg))
+                }
+                None => ffi::sqlite3_busy_handler(self.db(), None, ptr::null_mut()),
+            }
+        };
+        try!(self.decode_result(r));
+        self.busy_handler = callback;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate tempdir;
+
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use self::tempdir::TempDir;
+    use {Connection, Error, ErrorCode};
+
+    fn locked_pair(name: &str) -> (TempDir, Connection, Connection) {
+        let temp_dir = TempDir::new(name).unwrap();
+        let path = temp_dir.path().join("test.db3");
+
+        let db1 = Connection::open(&path).unwrap();
+        let db2 = Connection::open(&path).unwrap();
+        db1.execute_batch("CREATE TABLE foo(x INTEGER); BEGIN EXCLUSIVE").unwrap();
+        (temp_dir, db1, db2)
+    }
+
+    fn assert_busy(err: Error) {
+        match err {
+            Error::SqliteFailure(err, _) => assert_eq!(ErrorCode::DatabaseBusy, err.code),
+            err => panic!("Unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn test_busy_timeout() {
+        let (_temp_dir, _db1, db2) = locked_pair("test_busy_timeout");
+        db2.busy_timeout(Duration::from_millis(100)).unwrap();
+
+        let start = Instant::now();
+        assert_busy(db2.execute("INSERT INTO foo VALUES (1)", &[]).unwrap_err());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_auto_retry() {
+        let (_temp_dir, db1, db2) = locked_pair("test_auto_retry");
+        db2.busy_timeout(Duration::from_secs(0)).unwrap();
+        assert_busy(db2.execute("INSERT INTO foo VALUES (1)", &[]).unwrap_err());
+
+        db2.set_auto_retry(3, Duration::from_millis(10));
+        let start = Instant::now();
+        assert_busy(db2.execute("INSERT INTO foo VALUES (1)", &[]).unwrap_err());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        db2.set_auto_retry(500, Duration::from_millis(10));
+        let committer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            db1.execute_batch("COMMIT").unwrap();
+        });
+        assert_eq!(1, db2.execute("INSERT INTO foo VALUES (1)", &[]).unwrap());
+        committer.join().unwrap();
+    }
+
+    #[test]
+    fn test_auto_retry_not_in_transaction() {
+        let (_temp_dir, _db1, db2) = locked_pair("test_auto_retry_not_in_transaction");
+        db2.busy_timeout(Duration::from_secs(0)).unwrap();
+        db2.set_auto_retry(100, Duration::from_millis(10));
+
+        db2.execute_batch("BEGIN").unwrap();
+        let start = Instant::now();
+        assert_busy(db2.query_row("SELECT COUNT(*) FROM foo", &[], |r| r.get::<_, i64>(0))
+                        .unwrap_err());
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_busy_handler() {
+        let (_temp_dir, db1, db2) = locked_pair("test_busy_handler");
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler_calls = calls.clone();
+        db2.busy_handler(Some(move |count| {
+                handler_calls.fetch_add(1, Ordering::SeqCst);
+                count < 2
+            }))
+            .unwrap();
+        assert_busy(db2.execute("INSERT INTO foo VALUES (1)", &[]).unwrap_err());
+        assert_eq!(3, calls.load(Ordering::SeqCst));
+
+        db2.busy_handler(None::<fn(i32) -> bool>).unwrap();
+        assert_busy(db2.execute("INSERT INTO foo VALUES (1)", &[]).unwrap_err());
+        assert_eq!(3, calls.load(Ordering::SeqCst));
+
+        db1.execute_batch("COMMIT").unwrap();
+        db2.execute("INSERT INTO foo VALUES (1)", &[]).unwrap();
+    }
+}
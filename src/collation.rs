@@ -0,0 +1,188 @@
+//! Add, remove, or modify a collation
+use std::cmp::Ordering;
+use std::mem;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::slice;
+
+use ffi;
+
+use {Connection, InnerConnection, Result, str_to_cstring};
+
+unsafe extern "C" fn free_boxed_value<T>(p: *mut c_void) {
+    let _: Box<T> = Box::from_raw(mem::transmute(p));
+}
+
+impl Connection {
+    /// Add or modify a collation named `collation_name`, which compares two strings with
+    /// `x_compare`.
+    ///
+    /// The collation can then be used in SQL, e.g., `ORDER BY name COLLATE nocase_unicode` or
+    /// `CREATE TABLE foo (name TEXT COLLATE nocase_unicode)`. Registering a collation with the
+    /// name of an existing one replaces it; the previous closure is dropped, and the new one is
+    /// kept alive until it is replaced or removed or the connection is closed. Text that is not
+    /// valid UTF-8 is passed to `x_compare` with invalid sequences replaced by `U+FFFD`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn unicode_case_insensitive(conn: &Connection) -> Result<()> {
+    ///     try!(conn.create_collation("nocase_unicode", |a, b| {
+    ///         a.to_lowercase().cmp(&b.to_lowercase())
+    ///     }));
+    ///     let mut stmt = try!(conn.prepare("SELECT name FROM person \
+    ///                                       ORDER BY name COLLATE nocase_unicode"));
+    ///     // ...
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return Err if the collation could not be registered (e.g., because a statement
+    /// using the existing collation with the same name is active).
+    pub fn create_collation<C>(&self, collation_name: &str, x_compare: C) -> Result<()>
+        where C: Fn(&str, &str) -> Ordering + Send + 'static
+    {
+        self.db.borrow_mut().create_collation(collation_name, x_compare)
+    }
+
+    /// Remove a collation previously registered with `create_collation`.
+    ///
+    /// # Failure
+    ///
+    /// Will return Err if the collation could not be removed.
+    pub fn remove_collation(&self, collation_name: &str) -> Result<()> {
+        self.db.borrow_mut().remove_collation(collation_name)
+    }
+}
+
+impl InnerConnection {
+    fn create_collation<C>(&mut self, collation_name: &str, x_compare: C) -> Result<()>
+        where C: Fn(&str, &str) -> Ordering + Send + 'static
+    {
+        unsafe extern "C" fn call_boxed_closure<C>(arg1: *mut c_void,
+                                                   arg2: c_int,
+                                                   arg3: *const c_void,
+                                                   arg4: c_int,
+                                                   arg5: *const c_void)
+                                                   -> c_int
+            where C: Fn(&str, &str) -> Ordering
+        {
+            let boxed_f: *mut C = mem::transmute(arg1);
+            assert!(!boxed_f.is_null(), "Internal error - null function pointer");
+
+            let s1 = String::from_utf8_lossy(slice::from_raw_parts(arg3 as *const u8,
+                                                                    arg2 as usize));
+            let s2 = String::from_utf8_lossy(slice::from_raw_parts(arg5 as *const u8,
+                                                                    arg4 as usize));
+            match (*boxed_f)(&s1, &s2) {
+                Ordering::Less => -1,
+                Ordering::Equal => 0,
+                Ordering::Greater => 1,
+            }
+        }
+
+        let boxed_f: *mut C = Box::into_raw(Box::new(x_compare));
+        let c_name = try!(str_to_cstring(collation_name));
+        let r = unsafe {
+            ffi::sqlite3_create_collation_v2(self.db(),
+                                             c_name.as_ptr(),
+                                             ffi::SQLITE_UTF8,
+                                             mem::transmute(boxed_f),
+                                             Some(call_boxed_closure::<C>),
+                                             Some(free_boxed_value::<C>))
+        };
+        if r != ffi::SQLITE_OK {
+            // SQLite does not call the destructor if registration fails.
+            unsafe { free_boxed_value::<C>(mem::transmute(boxed_f)) };
+        }
+        self.decode_result(r)
+    }
+
+    fn remove_collation(&mut self, collation_name: &str) -> Result<()> {
+        let c_name = try!(str_to_cstring(collation_name));
+        let r = unsafe {
+            ffi::sqlite3_create_collation_v2(self.db(),
+                                             c_name.as_ptr(),
+                                             ffi::SQLITE_UTF8,
+                                             ptr::null_mut(),
+                                             None,
+                                             None)
+        };
+        self.decode_result(r)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use Connection;
+
+    fn names(db: &Connection, sql: &str) -> Vec<String> {
+        let mut stmt = db.prepare(sql).unwrap();
+        let rows = stmt.query_map(&[], |row| row.get(0)).unwrap();
+        rows.map(|r| r.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_create_collation() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (name TEXT);
+                          INSERT INTO foo VALUES ('b');
+                          INSERT INTO foo VALUES ('Ä');
+                          INSERT INTO foo VALUES ('a');
+                          INSERT INTO foo VALUES ('ä');")
+            .unwrap();
+
+        db.create_collation("reverse", |a: &str, b: &str| b.cmp(a)).unwrap();
+        assert_eq!(vec!["ä", "Ä", "b", "a"],
+                   names(&db, "SELECT name FROM foo ORDER BY name COLLATE reverse"));
+
+        db.create_collation("nocase_unicode",
+                              |a: &str, b: &str| a.to_lowercase().cmp(&b.to_lowercase()))
+            .unwrap();
+        let count: i64 = db.query_row("SELECT COUNT(*) FROM foo WHERE name = 'ä' \
+                                       COLLATE nocase_unicode",
+                                      &[],
+                                      |r| r.get(0))
+            .unwrap();
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn test_collation_is_dropped() {
+        struct DropCounter(Arc<AtomicUsize>);
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let db = Connection::open_in_memory().unwrap();
+
+        let counter = DropCounter(drops.clone());
+        db.create_collation("c", move |a: &str, b: &str| {
+                let _ = &counter;
+                a.cmp(b)
+            })
+            .unwrap();
+        // Replacing the collation drops the first closure.
+        let counter = DropCounter(drops.clone());
+        db.create_collation("c", move |a: &str, b: &str| {
+                let _ = &counter;
+                b.cmp(a)
+            })
+            .unwrap();
+        assert_eq!(1, drops.load(Ordering::SeqCst));
+
+        db.remove_collation("c").unwrap();
+        assert_eq!(2, drops.load(Ordering::SeqCst));
+        assert!(db.prepare("SELECT 'a' < 'b' COLLATE c").is_err());
+    }
+}
@@ -0,0 +1,56 @@
+//! Process-wide memory configuration, applied before SQLite is initialized.
+
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+
+use ffi;
+use Result;
+use error::error_from_sqlite_code;
+
+fn check(rc: c_int) -> Result<()> {
+    if rc == ffi::SQLITE_OK {
+        Ok(())
+    } else {
+        Err(error_from_sqlite_code(rc, None))
+    }
+}
+
+/// Set the default size of the lookaside memory allocator of every new connection:
+/// `n_slots` slots of `slot_size` bytes each. A `slot_size` or `n_slots` of zero disables
+/// lookaside memory.
+///
+/// This function is marked unsafe because it is not threadsafe: no other SQLite calls may be
+/// made while it is running, and multiple threads may not call it simultaneously.
+///
+/// cf [SQLITE_CONFIG_LOOKASIDE](https://www.sqlite.org/c3ref/c_config_covering_index_scan.html#sqliteconfiglookaside).
+///
+/// # Failure
+///
+/// SQLite can only be configured before it is initialized, which happens when the first
+/// connection is opened. Will return `Err` with `SQLITE_MISUSE` if SQLite has already been
+/// initialized.
+pub unsafe fn set_lookaside(slot_size: i32, n_slots: i32) -> Result<()> {
+    check(ffi::sqlite3_config(ffi::SQLITE_CONFIG_LOOKASIDE, slot_size as c_int, n_slots as c_int))
+}
+
+/// Set the size of the page cache memory that each new connection allocates up front:
+/// `n_pages` pages of `page_size` bytes each, allocated via `sqlite3_malloc`. `page_size`
+/// should be the database page size plus a small per-page header (a few hundred bytes). Pages
+/// that do not fit in this memory are allocated individually as needed.
+///
+/// This function is marked unsafe because it is not threadsafe: no other SQLite calls may be
+/// made while it is running, and multiple threads may not call it simultaneously.
+///
+/// cf [SQLITE_CONFIG_PAGECACHE](https://www.sqlite.org/c3ref/c_config_covering_index_scan.html#sqliteconfigpagecache).
+/// SQLite versions prior to 3.12.0 ignore this setting.
+///
+/// # Failure
+///
+/// Will return `Err` with `SQLITE_MISUSE` if SQLite has already been initialized.
+pub unsafe fn set_page_cache(page_size: i32, n_pages: i32) -> Result<()> {
+    let buffer: *mut c_void = ptr::null_mut();
+    check(ffi::sqlite3_config(ffi::SQLITE_CONFIG_PAGECACHE,
+                              buffer,
+                              page_size as c_int,
+                              n_pages as c_int))
+}
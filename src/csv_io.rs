@@ -0,0 +1,358 @@
+//! Import CSV data into SQLite tables and export query results as CSV.
+use std::io;
+use std::path::Path;
+
+use csv;
+
+use {Connection, Error, Result, Statement};
+use quote_identifier;
+use types::{ToSql, Value};
+
+/// Column type inferred from the sampled rows of a CSV file. The variants are ordered so that
+/// `max` widens to the type that can hold both values.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,PartialOrd,Ord)]
+enum ColumnType {
+    Integer,
+    Real,
+    Text,
+}
+
+impl ColumnType {
+    fn of(field: &str) -> Option<ColumnType> {
+        if field.is_empty() {
+            None
+        } else if field.parse::<i64>().is_ok() {
+            Some(ColumnType::Integer)
+        } else if field.parse::<f64>().map(|f| f.is_finite()).unwrap_or(false) {
+            Some(ColumnType::Real)
+        } else {
+            Some(ColumnType::Text)
+        }
+    }
+
+    fn declared(&self) -> &'static str {
+        match *self {
+            ColumnType::Integer => "INTEGER",
+            ColumnType::Real => "REAL",
+            ColumnType::Text => "TEXT",
+        }
+    }
+
+    fn value(&self, field: &str) -> Value {
+        if field.is_empty() {
+            return Value::Null;
+        }
+        match *self {
+            ColumnType::Integer => field.parse().ok().map(Value::Integer),
+            ColumnType::Real => field.parse().ok().map(Value::Real),
+            ColumnType::Text => None,
+        }
+        .unwrap_or_else(|| Value::Text(field.to_owned()))
+    }
+}
+
+impl Connection {
+    /// Create the table `table` and fill it with the CSV data read from `reader`.
+    ///
+    /// The first record of the CSV data is used for the column names. The column types are
+    /// inferred from the first `sample_rows` records: a column is declared `INTEGER` if all of
+    /// its sampled values are integers, `REAL` if they are all numbers, and `TEXT` otherwise.
+    /// Empty fields are inserted as `NULL` and do not take part in the inference. A value
+    /// after the sampled records that does not match its column type is inserted as text.
+    ///
+    /// On success, returns the number of rows that were inserted. Consider calling this inside
+    /// a transaction when importing large files.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use std::fs::File;
+    /// fn import_people(conn: &Connection) -> Result<usize> {
+    ///     let file = File::open("people.csv").unwrap();
+    ///     conn.import_csv_typed(file, "people", 100)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the CSV data cannot be parsed (including records that do not have
+    /// the same number of fields as the header), if the table cannot be created (e.g., it
+    /// already exists), or if the underlying SQLite call fails.
+    pub fn import_csv_typed<R: io::Read>(&self,
+                                         reader: R,
+                                         table: &str,
+                                         sample_rows: usize)
+                                         -> Result<usize> {
+        let mut reader = csv::Reader::from_reader(reader);
+        let headers = try!(reader.headers().map_err(Error::CsvError)).clone();
+
+        let mut records = reader.records();
+        let mut sample = Vec::with_capacity(sample_rows);
+        while sample.len() < sample_rows {
+            match records.next() {
+                Some(record) => sample.push(try!(record.map_err(Error::CsvError))),
+                None => break,
+            }
+        }
+
+        let mut types: Vec<Option<ColumnType>> = vec![None; headers.len()];
+        for record in &sample {
+            for (ty, field) in types.iter_mut().zip(record.iter()) {
+                *ty = ::std::cmp::max(*ty, ColumnType::of(field));
+            }
+        }
+        let types: Vec<ColumnType> = types.into_iter()
+            .map(|ty| ty.unwrap_or(ColumnType::Text))
+            .collect();
+
+        let columns: Vec<String> = headers.iter()
+            .zip(&types)
+            .map(|(name, ty)| format!("{} {}", quote_identifier(name), ty.declared()))
+            .collect();
+        try!(self.execute_batch(&format!("CREATE TABLE {} ({})",
+                                         quote_identifier(table),
+                                         columns.join(", "))));
+
+        let placeholders = vec!["?"; headers.len()].join(", ");
+        let mut stmt = try!(self.prepare(&format!("INSERT INTO {} VALUES ({})",
+                                                  quote_identifier(table),
+                                                  placeholders)));
+        let mut count = 0;
+        let rest = records.map(|record| record.map_err(Error::CsvError));
+        for record in sample.into_iter().map(Ok).chain(rest) {
+            let record = try!(record);
+            let values: Vec<Value> = types.iter()
+                .zip(record.iter())
+                .map(|(ty, field)| ty.value(field))
+                .collect();
+            let params: Vec<&ToSql> = values.iter().map(|v| v as &ToSql).collect();
+            try!(stmt.execute(&params));
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl<'conn> Statement<'conn> {
+    /// Execute the prepared statement and write the resulting rows to the CSV file at `path`,
+    /// which is created if it does not exist and truncated if it does.
+    ///
+    /// If `has_headers` is true, the first record of the file contains the column names.
+    /// `NULL` values are written as empty fields, and text and blob values are written as-is.
+    /// The file is flushed before returning.
+    ///
+    /// On success, returns the number of rows that were written (not counting the header).
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn export_people(conn: &Connection) -> Result<usize> {
+    ///     let mut stmt = try!(conn.prepare("SELECT id, name FROM people"));
+    ///     stmt.query_to_csv_file(&[], "people.csv", true)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails, if the file cannot be created or
+    /// written, or if the underlying SQLite call fails.
+    pub fn query_to_csv_file<P: AsRef<Path>>(&mut self,
+                                             params: &[&ToSql],
+                                             path: P,
+                                             has_headers: bool)
+                                             -> Result<usize> {
+        self.query_to_csv_file_with(params, path, has_headers, &csv::WriterBuilder::new())
+    }
+
+    /// Like `query_to_csv_file`, but the CSV output is configured by `builder` (e.g., its
+    /// delimiter, `quote_style` or `double_quote` setting) instead of using the `csv` crate's
+    /// defaults, which produce RFC 4180 output.
+    ///
+    /// The `has_headers` setting of `builder` is ignored; the header row is controlled by
+    /// `has_headers`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # extern crate csv;
+    /// # extern crate rusqlite;
+    /// # use rusqlite::{Connection, Result};
+    /// use csv::{QuoteStyle, WriterBuilder};
+    ///
+    /// fn export_quoted(conn: &Connection) -> Result<usize> {
+    ///     let mut stmt = try!(conn.prepare("SELECT id, name FROM people"));
+    ///     let mut builder = WriterBuilder::new();
+    ///     builder.quote_style(QuoteStyle::Always);
+    ///     stmt.query_to_csv_file_with(&[], "people.csv", true, &builder)
+    /// }
+    /// # fn main() {}
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails, if the file cannot be created or
+    /// written, or if the underlying SQLite call fails.
+    pub fn query_to_csv_file_with<P: AsRef<Path>>(&mut self,
+                                                  params: &[&ToSql],
+                                                  path: P,
+                                                  has_headers: bool,
+                                                  builder: &csv::WriterBuilder)
+                                                  -> Result<usize> {
+        let headers: Vec<String> = self.column_names().into_iter().map(String::from).collect();
+        let column_count = headers.len();
+        let mut writer = try!(builder.from_path(path).map_err(Error::CsvError));
+        if has_headers {
+            try!(writer.write_record(&headers).map_err(Error::CsvError));
+        }
+
+        let mut count = 0;
+        let mut rows = try!(self.query(params));
+        while let Some(row) = rows.next() {
+            let row = try!(row);
+            let mut record: Vec<Vec<u8>> = Vec::with_capacity(column_count);
+            for i in 0..column_count as i32 {
+                record.push(match try!(row.get_checked(i)) {
+                    Value::Null => Vec::new(),
+                    Value::Integer(i) => i.to_string().into_bytes(),
+                    Value::Real(f) => f.to_string().into_bytes(),
+                    Value::Text(s) => s.into_bytes(),
+                    Value::Blob(b) => b,
+                });
+            }
+            try!(writer.write_record(&record).map_err(Error::CsvError));
+            count += 1;
+        }
+        try!(writer.flush().map_err(|err| Error::CsvError(err.into())));
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate tempdir;
+
+    use std::fs::File;
+    use std::io::Read;
+
+    use self::tempdir::TempDir;
+    use csv::{QuoteStyle, WriterBuilder};
+    use Connection;
+
+    fn declared_types(db: &Connection, table: &str) -> Vec<(String, String)> {
+        let mut stmt = db.prepare(&format!("PRAGMA table_info({})", table)).unwrap();
+        let rows = stmt.query_map(&[], |row| (row.get(1), row.get(2))).unwrap();
+        rows.map(|r| r.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_import_csv_typed() {
+        let db = Connection::open_in_memory().unwrap();
+        let data = "id,price,name\n1,2.5,apple\n2,3,pear\n3,,\"plum, red\"\n";
+        assert_eq!(3, db.import_csv_typed(data.as_bytes(), "fruit", 10).unwrap());
+
+        assert_eq!(vec![("id".to_owned(), "INTEGER".to_owned()),
+                        ("price".to_owned(), "REAL".to_owned()),
+                        ("name".to_owned(), "TEXT".to_owned())],
+                   declared_types(&db, "fruit"));
+
+        let mut stmt = db.prepare("SELECT typeof(id), typeof(price), name FROM fruit ORDER BY id")
+            .unwrap();
+        let rows: Vec<(String, String, String)> = stmt.query_map(&[], |row| {
+                (row.get(0), row.get(1), row.get(2))
+            })
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(("integer".to_owned(), "real".to_owned(), "apple".to_owned()), rows[0]);
+        assert_eq!(("integer".to_owned(), "real".to_owned(), "pear".to_owned()), rows[1]);
+        assert_eq!(("integer".to_owned(), "null".to_owned(), "plum, red".to_owned()), rows[2]);
+    }
+
+    #[test]
+    fn test_import_csv_typed_sample_size() {
+        let db = Connection::open_in_memory().unwrap();
+        let data = "a,b\n1,x\n2,\nthree,\n";
+        assert_eq!(3, db.import_csv_typed(data.as_bytes(), "t", 2).unwrap());
+
+        // "three" is not sampled, so the column stays INTEGER and the value is stored as text.
+        assert_eq!(vec![("a".to_owned(), "INTEGER".to_owned()),
+                        ("b".to_owned(), "TEXT".to_owned())],
+                   declared_types(&db, "t"));
+        let a: String = db.query_row("SELECT a FROM t WHERE rowid = 3", &[], |r| r.get(0))
+            .unwrap();
+        assert_eq!("three", a);
+    }
+
+    #[test]
+    fn test_import_csv_typed_ragged() {
+        let db = Connection::open_in_memory().unwrap();
+        let data = "a,b\n1,2\n3\n";
+        assert!(db.import_csv_typed(data.as_bytes(), "t", 10).is_err());
+    }
+
+    #[test]
+    fn test_query_to_csv_file() {
+        let temp_dir = TempDir::new("test_query_to_csv_file").unwrap();
+        let path = temp_dir.path().join("out.csv");
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (id INTEGER, price REAL, name TEXT);
+                          INSERT INTO foo VALUES (1, 2.5, 'apple');
+                          INSERT INTO foo VALUES (2, NULL, 'plum, red');
+                          INSERT INTO foo VALUES (3, 4.0, 'pear');")
+            .unwrap();
+
+        let mut stmt = db.prepare("SELECT id, price, name FROM foo WHERE id < ? ORDER BY id")
+            .unwrap();
+        assert_eq!(2, stmt.query_to_csv_file(&[&3], &path, true).unwrap());
+        let mut contents = String::new();
+        File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!("id,price,name\n1,2.5,apple\n2,,\"plum, red\"\n", contents);
+
+        // Writing again truncates the file.
+        assert_eq!(1, stmt.query_to_csv_file(&[&2], &path, false).unwrap());
+        let mut contents = String::new();
+        File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!("1,2.5,apple\n", contents);
+
+        // The exported file can be imported back.
+        let mut stmt = db.prepare("SELECT id, price, name FROM foo ORDER BY id").unwrap();
+        stmt.query_to_csv_file(&[], &path, true).unwrap();
+        let file = File::open(&path).unwrap();
+        assert_eq!(3, db.import_csv_typed(file, "bar", 10).unwrap());
+        let count: i64 = db.query_row("SELECT COUNT(*) FROM foo NATURAL JOIN bar", &[], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn test_query_to_csv_file_with() {
+        let temp_dir = TempDir::new("test_query_to_csv_file_with").unwrap();
+        let path = temp_dir.path().join("out.csv");
+        let read = || {
+            let mut contents = String::new();
+            File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+            contents
+        };
+
+        let db = Connection::open_in_memory().unwrap();
+        let mut stmt = db.prepare("SELECT 1 AS id, 'say \"hi\"' AS msg").unwrap();
+
+        let mut builder = WriterBuilder::new();
+        builder.quote_style(QuoteStyle::Necessary);
+        stmt.query_to_csv_file_with(&[], &path, true, &builder).unwrap();
+        assert_eq!("id,msg\n1,\"say \"\"hi\"\"\"\n", read());
+
+        builder.quote_style(QuoteStyle::Always);
+        stmt.query_to_csv_file_with(&[], &path, true, &builder).unwrap();
+        assert_eq!("\"id\",\"msg\"\n\"1\",\"say \"\"hi\"\"\"\n", read());
+
+        builder.double_quote(false);
+        stmt.query_to_csv_file_with(&[], &path, false, &builder).unwrap();
+        assert_eq!("\"1\",\"say \\\"hi\\\"\"\n", read());
+    }
+}
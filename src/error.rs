@@ -4,7 +4,10 @@ use std::path::PathBuf;
 use std::str;
 use std::os::raw::c_int;
 use {ffi, errmsg_to_string};
+use ffi::ErrorCode;
 use types::Type;
+#[cfg(feature = "csv")]
+use csv;
 
 /// Old name for `Error`. `SqliteError` is deprecated.
 #[deprecated(since = "0.6.0", note = "Use Error instead")]
@@ -77,6 +80,151 @@ pub enum Error {
 
     /// Error available for the implementors of the `ToSql` trait.
     ToSqlConversionFailure(Box<error::Error + Send + Sync>),
+
+    /// Error when CSV data cannot be read or parsed (e.g., by `Connection::import_csv_typed`).
+    #[cfg(feature = "csv")]
+    CsvError(csv::Error),
+}
+
+/// Broad classification of an `Error`, intended for deciding whether a failed operation is worth
+/// retrying. See `Error::category`.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum ErrorCategory {
+    /// The operation may succeed if it is retried (`SQLITE_BUSY`, `SQLITE_LOCKED` or
+    /// `SQLITE_INTERRUPT`).
+    Transient,
+
+    /// A constraint was violated; retrying the same operation will fail again.
+    Constraint,
+
+    /// The database schema changed; the statement should be prepared again before retrying.
+    SchemaChanged,
+
+    /// The database file or the I/O layer is in a state that retrying will not fix
+    /// (`SQLITE_CORRUPT`, `SQLITE_IOERR`, `SQLITE_FULL`, ...).
+    Fatal,
+
+    /// Any other error, such as API misuse or a type conversion failure.
+    Other,
+}
+
+/// The kind of constraint whose violation caused an `SQLITE_CONSTRAINT` error, taken from the
+/// [extended result code](https://www.sqlite.org/rescode.html#extrc). See
+/// `Error::constraint_violation`.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum ConstraintKind {
+    /// A `CHECK` constraint failed (`SQLITE_CONSTRAINT_CHECK`).
+    Check,
+    /// A commit hook caused the transaction to roll back (`SQLITE_CONSTRAINT_COMMITHOOK`).
+    CommitHook,
+    /// A foreign key constraint failed (`SQLITE_CONSTRAINT_FOREIGNKEY`).
+    ForeignKey,
+    /// An extension function reported a constraint error (`SQLITE_CONSTRAINT_FUNCTION`).
+    Function,
+    /// A `NOT NULL` constraint failed (`SQLITE_CONSTRAINT_NOTNULL`).
+    NotNull,
+    /// A `PRIMARY KEY` constraint failed (`SQLITE_CONSTRAINT_PRIMARYKEY`).
+    PrimaryKey,
+    /// `RAISE(ABORT, ...)`, `RAISE(ROLLBACK, ...)` or `RAISE(FAIL, ...)` was called in a trigger
+    /// (`SQLITE_CONSTRAINT_TRIGGER`).
+    Trigger,
+    /// A `UNIQUE` constraint failed (`SQLITE_CONSTRAINT_UNIQUE`).
+    Unique,
+    /// A virtual table reported a constraint error (`SQLITE_CONSTRAINT_VTAB`).
+    VTab,
+    /// A rowid is not unique (`SQLITE_CONSTRAINT_ROWID`).
+    RowId,
+    /// The extended result code does not identify the constraint (e.g., because extended
+    /// result codes are not enabled, or because the SQLite version is newer than rusqlite).
+    Other,
+}
+
+impl Error {
+    /// Classify this error using its SQLite result code. Errors that do not come from SQLite
+    /// (e.g., `InvalidColumnType`) are classified as `ErrorCategory::Other`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Error, ErrorCategory, Result};
+    /// fn insert_with_retry(conn: &Connection) -> Result<i32> {
+    ///     loop {
+    ///         match conn.execute("INSERT INTO foo (x) VALUES (1)", &[]) {
+    ///             Err(ref err) if err.category() == ErrorCategory::Transient => continue,
+    ///             result => return result,
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn category(&self) -> ErrorCategory {
+        let err = match *self {
+            Error::SqliteFailure(ref err, _) => err,
+            _ => return ErrorCategory::Other,
+        };
+        match err.code {
+            ErrorCode::DatabaseBusy |
+            ErrorCode::DatabaseLocked |
+            ErrorCode::OperationInterrupted => ErrorCategory::Transient,
+            ErrorCode::ConstraintViolation => ErrorCategory::Constraint,
+            ErrorCode::SchemaChanged => ErrorCategory::SchemaChanged,
+            ErrorCode::InternalMalfunction |
+            ErrorCode::OutOfMemory |
+            ErrorCode::SystemIOFailure |
+            ErrorCode::DatabaseCorrupt |
+            ErrorCode::DiskFull |
+            ErrorCode::CannotOpen |
+            ErrorCode::FileLockingProtocolFailed |
+            ErrorCode::NotADatabase => ErrorCategory::Fatal,
+            _ => ErrorCategory::Other,
+        }
+    }
+
+    /// Get the extended result code of this error (e.g., `SQLITE_CONSTRAINT_UNIQUE`), or `None`
+    /// if the error does not come from SQLite.
+    pub fn extended_code(&self) -> Option<c_int> {
+        match *self {
+            Error::SqliteFailure(ref err, _) => Some(err.extended_code),
+            _ => None,
+        }
+    }
+
+    /// If this error is a constraint violation (`SQLITE_CONSTRAINT`), get the kind of
+    /// constraint that was violated.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, ConstraintKind, Result};
+    /// fn insert_user(conn: &Connection, name: &str) -> Result<bool> {
+    ///     match conn.execute("INSERT INTO users (name) VALUES (?)", &[&name]) {
+    ///         Ok(_) => Ok(true),
+    ///         // The name is already taken.
+    ///         Err(ref err) if err.constraint_violation() == Some(ConstraintKind::Unique) => {
+    ///             Ok(false)
+    ///         }
+    ///         Err(err) => Err(err),
+    ///     }
+    /// }
+    /// ```
+    pub fn constraint_violation(&self) -> Option<ConstraintKind> {
+        let err = match *self {
+            Error::SqliteFailure(ref err, _) if err.code == ErrorCode::ConstraintViolation => err,
+            _ => return None,
+        };
+        Some(match err.extended_code >> 8 {
+                 1 => ConstraintKind::Check,
+                 2 => ConstraintKind::CommitHook,
+                 3 => ConstraintKind::ForeignKey,
+                 4 => ConstraintKind::Function,
+                 5 => ConstraintKind::NotNull,
+                 6 => ConstraintKind::PrimaryKey,
+                 7 => ConstraintKind::Trigger,
+                 8 => ConstraintKind::Unique,
+                 9 => ConstraintKind::VTab,
+                 10 => ConstraintKind::RowId,
+                 _ => ConstraintKind::Other,
+             })
+    }
 }
 
 impl From<str::Utf8Error> for Error {
@@ -132,6 +280,8 @@ impl fmt::Display for Error {
             #[cfg(feature = "functions")]
             Error::UserFunctionError(ref err) => err.fmt(f),
             Error::ToSqlConversionFailure(ref err) => err.fmt(f),
+            #[cfg(feature = "csv")]
+            Error::CsvError(ref err) => err.fmt(f),
         }
     }
 }
@@ -160,10 +310,12 @@ impl error::Error for Error {
             #[cfg(feature = "functions")]
             Error::UserFunctionError(ref err) => err.description(),
             Error::ToSqlConversionFailure(ref err) => err.description(),
+            #[cfg(feature = "csv")]
+            Error::CsvError(ref err) => err.description(),
         }
     }
 
-    fn cause(&self) -> Option<&error::Error> {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
         match *self {
             Error::SqliteFailure(ref err, _) => Some(err),
             Error::Utf8Error(ref err) => Some(err),
@@ -188,6 +340,9 @@ impl error::Error for Error {
 
             Error::FromSqlConversionFailure(_, _, ref err) |
             Error::ToSqlConversionFailure(ref err) => Some(&**err),
+
+            #[cfg(feature = "csv")]
+            Error::CsvError(ref err) => Some(err),
         }
     }
 }
@@ -206,3 +361,87 @@ pub fn error_from_handle(db: *mut ffi::sqlite3, code: c_int) -> Error {
     };
     error_from_sqlite_code(code, message)
 }
+
+#[cfg(test)]
+mod test {
+    extern crate tempdir;
+
+    use self::tempdir::TempDir;
+    use {ffi, Connection, Error};
+    use super::{ConstraintKind, ErrorCategory};
+
+    #[test]
+    fn test_category_busy() {
+        let temp_dir = TempDir::new("test_category_busy").unwrap();
+        let path = temp_dir.path().join("test.db3");
+
+        let db1 = Connection::open(&path).unwrap();
+        let db2 = Connection::open(&path).unwrap();
+        db1.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        db2.execute_batch("PRAGMA busy_timeout = 0").unwrap();
+
+        db1.execute_batch("BEGIN EXCLUSIVE").unwrap();
+        let err = db2.execute("INSERT INTO foo VALUES (1)", &[]).unwrap_err();
+        assert_eq!(ErrorCategory::Transient, err.category());
+        db1.execute_batch("COMMIT").unwrap();
+    }
+
+    #[test]
+    fn test_category_constraint() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER UNIQUE)").unwrap();
+        db.execute("INSERT INTO foo VALUES (1)", &[]).unwrap();
+
+        let err = db.execute("INSERT INTO foo VALUES (1)", &[]).unwrap_err();
+        assert_eq!(ErrorCategory::Constraint, err.category());
+    }
+
+    #[test]
+    fn test_constraint_violation() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("PRAGMA foreign_keys = ON;
+                          CREATE TABLE parent(id INTEGER PRIMARY KEY);
+                          CREATE TABLE foo(x INTEGER UNIQUE CHECK (x > 0) NOT NULL,
+                                           p INTEGER REFERENCES parent(id));
+                          INSERT INTO foo (x) VALUES (1);")
+            .unwrap();
+
+        for &(sql, kind) in &[("INSERT INTO foo (x) VALUES (1)", ConstraintKind::Unique),
+                              ("INSERT INTO foo (x) VALUES (-1)", ConstraintKind::Check),
+                              ("INSERT INTO foo (x) VALUES (NULL)", ConstraintKind::NotNull),
+                              ("INSERT INTO foo (x, p) VALUES (2, 1)",
+                               ConstraintKind::ForeignKey)] {
+            let err = db.execute(sql, &[]).unwrap_err();
+            assert_eq!(Some(kind), err.constraint_violation(), "{}", sql);
+        }
+        let err = db.execute("INSERT INTO foo (x) VALUES (1)", &[]).unwrap_err();
+        // SQLITE_CONSTRAINT_UNIQUE
+        assert_eq!(Some(ffi::SQLITE_CONSTRAINT | (8 << 8)), err.extended_code());
+
+        let err = db.prepare("SELECT * FROM no_such_table").unwrap_err();
+        assert_eq!(None, err.constraint_violation());
+        assert_eq!(Some(ffi::SQLITE_ERROR), err.extended_code());
+        assert_eq!(None, Error::QueryReturnedNoRows.extended_code());
+    }
+
+    #[test]
+    fn test_source() {
+        use std::error::Error as StdError;
+        use std::ffi::NulError;
+
+        let db = Connection::open_in_memory().unwrap();
+        let err = db.prepare("SELECT 1\0").unwrap_err();
+        assert!(err.source().unwrap().downcast_ref::<NulError>().is_some());
+
+        let err = db.prepare("SELECT * FROM no_such_table").unwrap_err();
+        let source = err.source().unwrap().downcast_ref::<ffi::Error>().unwrap();
+        assert_eq!(ffi::ErrorCode::Unknown, source.code);
+
+        assert!(Error::QueryReturnedNoRows.source().is_none());
+    }
+
+    #[test]
+    fn test_category_other() {
+        assert_eq!(ErrorCategory::Other, Error::QueryReturnedNoRows.category());
+    }
+}
@@ -182,6 +182,29 @@ impl<'a> ValueRef<'a> {
     }
 }
 
+/// The text encoding a user-defined function prefers for its arguments.
+///
+/// SQLite converts text arguments to the preferred encoding before calling the function, so
+/// choosing the database's encoding (see `PRAGMA encoding`) avoids a conversion per call.
+/// Either way, `Context` presents text arguments as UTF-8 `&str`s, and text results are
+/// converted to the database's encoding by SQLite.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum FunctionEncoding {
+    /// UTF-8, the default.
+    Utf8,
+    /// UTF-16 in the native byte order.
+    Utf16,
+}
+
+impl FunctionEncoding {
+    fn flags(&self) -> c_int {
+        match *self {
+            FunctionEncoding::Utf8 => ffi::SQLITE_UTF8,
+            FunctionEncoding::Utf16 => ffi::SQLITE_UTF16,
+        }
+    }
+}
+
 unsafe extern "C" fn free_boxed_value<T>(p: *mut c_void) {
     let _: Box<T> = Box::from_raw(mem::transmute(p));
 }
@@ -226,6 +249,20 @@ impl<'a> Context<'a> {
                                               })
     }
 
+    /// Returns the `idx`th argument as a `ValueRef` borrowing the argument's data.
+    ///
+    /// Unlike `get::<Vec<u8>>` or `get::<String>`, this does not copy TEXT or BLOB arguments,
+    /// so a function can scan large values without allocating. The borrowed data is only
+    /// valid for the duration of the function call.
+    ///
+    /// # Failure
+    ///
+    /// Will panic if `idx` is greater than or equal to `self.len()`.
+    pub fn get_raw(&self, idx: usize) -> ValueRef {
+        let arg = self.args[idx];
+        unsafe { ValueRef::from_value(arg) }
+    }
+
     /// Sets the auxilliary data associated with a particular parameter. See
     /// https://www.sqlite.org/c3ref/get_auxdata.html for a discussion of
     /// this feature, or the unit tests of this module for an example.
@@ -315,10 +352,36 @@ impl Connection {
                                         -> Result<()>
         where F: FnMut(&Context) -> Result<T>,
               T: ToSql
+    {
+        self.create_scalar_function_with_encoding(fn_name,
+                                                  n_arg,
+                                                  deterministic,
+                                                  FunctionEncoding::Utf8,
+                                                  x_func)
+    }
+
+    /// Attach a user-defined scalar function to this database connection, like
+    /// `create_scalar_function`, that prefers its arguments in the given text `encoding`.
+    ///
+    /// A function can be registered under the same name and number of arguments once per
+    /// encoding; SQLite calls the one whose encoding best matches the database's.
+    ///
+    /// # Failure
+    ///
+    /// Will return Err if the function could not be attached to the connection.
+    pub fn create_scalar_function_with_encoding<F, T>(&self,
+                                                      fn_name: &str,
+                                                      n_arg: c_int,
+                                                      deterministic: bool,
+                                                      encoding: FunctionEncoding,
+                                                      x_func: F)
+                                                      -> Result<()>
+        where F: FnMut(&Context) -> Result<T>,
+              T: ToSql
     {
         self.db
             .borrow_mut()
-            .create_scalar_function(fn_name, n_arg, deterministic, x_func)
+            .create_scalar_function(fn_name, n_arg, deterministic, encoding, x_func)
     }
 
     /// Attach a user-defined aggregate function to this database connection.
@@ -334,16 +397,69 @@ impl Connection {
                                               -> Result<()>
         where D: Aggregate<A, T>,
               T: ToSql
+    {
+        self.create_aggregate_function_with_encoding(fn_name,
+                                                     n_arg,
+                                                     deterministic,
+                                                     FunctionEncoding::Utf8,
+                                                     aggr)
+    }
+
+    /// Attach a user-defined aggregate function to this database connection, like
+    /// `create_aggregate_function`, that prefers its arguments in the given text `encoding`.
+    ///
+    /// # Failure
+    ///
+    /// Will return Err if the function could not be attached to the connection.
+    pub fn create_aggregate_function_with_encoding<A, D, T>(&self,
+                                                            fn_name: &str,
+                                                            n_arg: c_int,
+                                                            deterministic: bool,
+                                                            encoding: FunctionEncoding,
+                                                            aggr: D)
+                                                            -> Result<()>
+        where D: Aggregate<A, T>,
+              T: ToSql
     {
         self.db
             .borrow_mut()
-            .create_aggregate_function(fn_name, n_arg, deterministic, aggr)
+            .create_aggregate_function(fn_name, n_arg, deterministic, encoding, aggr)
+    }
+
+    /// Register a `now()` SQL function on this connection that returns the result of calling
+    /// `clock`, e.g., a number of seconds since the Unix epoch.
+    ///
+    /// Using `now()` instead of SQLite's `CURRENT_TIMESTAMP` or `'now'` makes it possible to
+    /// freeze time in tests. This only affects the `now()` function registered here; SQLite's
+    /// built-in date and time keywords and functions always use the system clock. Calling
+    /// `set_clock` again replaces the previous clock.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # extern crate rusqlite;
+    /// # extern crate time;
+    /// # use rusqlite::{Connection, Result};
+    /// # fn main() {}
+    /// fn use_system_clock(conn: &Connection) -> Result<()> {
+    ///     conn.set_clock(|| time::get_time().sec)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return Err if the function could not be attached to the connection.
+    pub fn set_clock<F>(&self, clock: F) -> Result<()>
+        where F: Fn() -> i64 + 'static
+    {
+        self.create_scalar_function("now", 0, false, move |_| Ok(clock()))
     }
 
     /// Removes a user-defined function from this database connection.
     ///
     /// `fn_name` and `n_arg` should match the name and number of arguments
-    /// given to `create_scalar_function` or `create_aggregate_function`.
+    /// given to `create_scalar_function` or `create_aggregate_function`. The function is removed
+    /// for every text encoding it was registered with.
     ///
     /// # Failure
     ///
@@ -358,6 +474,7 @@ impl InnerConnection {
                                     fn_name: &str,
                                     n_arg: c_int,
                                     deterministic: bool,
+                                    encoding: FunctionEncoding,
                                     x_func: F)
                                     -> Result<()>
         where F: FnMut(&Context) -> Result<T>,
@@ -388,7 +505,7 @@ impl InnerConnection {
 
         let boxed_f: *mut F = Box::into_raw(Box::new(x_func));
         let c_name = try!(str_to_cstring(fn_name));
-        let mut flags = ffi::SQLITE_UTF8;
+        let mut flags = encoding.flags();
         if deterministic {
             flags |= ffi::SQLITE_DETERMINISTIC;
         }
@@ -410,6 +527,7 @@ impl InnerConnection {
                                           fn_name: &str,
                                           n_arg: c_int,
                                           deterministic: bool,
+                                          encoding: FunctionEncoding,
                                           aggr: D)
                                           -> Result<()>
         where D: Aggregate<A, T>,
@@ -491,7 +609,7 @@ impl InnerConnection {
 
         let boxed_aggr: *mut D = Box::into_raw(Box::new(aggr));
         let c_name = try!(str_to_cstring(fn_name));
-        let mut flags = ffi::SQLITE_UTF8;
+        let mut flags = encoding.flags();
         if deterministic {
             flags |= ffi::SQLITE_DETERMINISTIC;
         }
@@ -515,7 +633,7 @@ impl InnerConnection {
             ffi::sqlite3_create_function_v2(self.db(),
                                             c_name.as_ptr(),
                                             n_arg,
-                                            ffi::SQLITE_UTF8,
+                                            ffi::SQLITE_ANY,
                                             ptr::null_mut(),
                                             None,
                                             None,
@@ -536,7 +654,7 @@ mod test {
     use std::f64::EPSILON;
 
     use {Connection, Error, Result};
-    use functions::{Aggregate, Context};
+    use functions::{Aggregate, Context, FunctionEncoding};
 
     fn half(ctx: &Context) -> Result<c_double> {
         assert!(ctx.len() == 1, "called with unexpected number of arguments");
@@ -553,6 +671,47 @@ mod test {
         assert!((3f64 - result.unwrap()).abs() < EPSILON);
     }
 
+    #[test]
+    fn test_function_get_raw() {
+        use types::ValueRef;
+
+        let db = Connection::open_in_memory().unwrap();
+        db.create_scalar_function("checksum", 1, true, |ctx| {
+                match ctx.get_raw(0) {
+                    ValueRef::Blob(b) => Ok(b.iter().fold(0i64, |sum, &b| sum + b as i64)),
+                    ValueRef::Text(s) => Ok(s.len() as i64),
+                    _ => Err(Error::InvalidFunctionParameterType(0, ctx.get_raw(0).data_type())),
+                }
+            })
+            .unwrap();
+
+        let blob: Vec<u8> = (0..1000).map(|i| (i % 256) as u8).collect();
+        let expected = blob.iter().fold(0i64, |sum, &b| sum + b as i64);
+        let result: i64 = db.query_row("SELECT checksum(?)", &[&blob], |r| r.get(0)).unwrap();
+        assert_eq!(expected, result);
+
+        let result: i64 = db.query_row("SELECT checksum('abc')", &[], |r| r.get(0)).unwrap();
+        assert_eq!(3, result);
+        let result: Result<i64> = db.query_row("SELECT checksum(1)", &[], |r| r.get(0));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_set_clock() {
+        let db = Connection::open_in_memory().unwrap();
+        db.set_clock(|| 1_500_000_000).unwrap();
+        db.execute_batch("CREATE TABLE foo (x INTEGER, created INTEGER DEFAULT (now()));
+                          INSERT INTO foo (x) VALUES (1);")
+            .unwrap();
+
+        let created: i64 = db.query_row("SELECT created FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!(1_500_000_000, created);
+
+        db.set_clock(|| 42).unwrap();
+        let now: i64 = db.query_row("SELECT now()", &[], |r| r.get(0)).unwrap();
+        assert_eq!(42, now);
+    }
+
     #[test]
     fn test_remove_function() {
         let db = Connection::open_in_memory().unwrap();
@@ -565,6 +724,30 @@ mod test {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_utf16_function() {
+        fn reverse(ctx: &Context) -> Result<String> {
+            Ok(try!(ctx.get::<String>(0)).chars().rev().collect())
+        }
+
+        for encoding in &["UTF-8", "UTF-16"] {
+            let db = Connection::open_in_memory().unwrap();
+            db.execute_batch(&format!("PRAGMA encoding = '{}'", encoding)).unwrap();
+            db.create_scalar_function_with_encoding("reverse",
+                                                      1,
+                                                      true,
+                                                      FunctionEncoding::Utf16,
+                                                      reverse)
+                .unwrap();
+            let result: String = db.query_row("SELECT reverse('Grüße, 世界')", &[], |r| r.get(0))
+                .unwrap();
+            assert_eq!("界世 ,eßürG", result);
+
+            db.remove_function("reverse", 1).unwrap();
+            assert!(db.query_row("SELECT reverse('a')", &[], |r| r.get::<_, String>(0)).is_err());
+        }
+    }
+
     // This implementation of a regexp scalar function uses SQLite's auxilliary data
     // (https://www.sqlite.org/c3ref/get_auxdata.html) to avoid recompiling the regular
     // expression multiple times within one query.
@@ -0,0 +1,682 @@
+//! Commit, Data Change and Rollback Notification Callbacks, the Progress Handler, and the
+//! Authorizer
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::str;
+
+use ffi;
+
+use {Connection, InnerConnection};
+
+/// The type of closure stored by `Connection::commit_hook`.
+pub type CommitHook = Box<FnMut() -> bool + Send>;
+/// The type of closure stored by `Connection::rollback_hook`.
+pub type RollbackHook = Box<FnMut() + Send>;
+/// The type of closure stored by `Connection::update_hook`.
+pub type UpdateHook = Box<FnMut(Action, &str, &str, i64) + Send>;
+/// The type of closure stored by `Connection::progress_handler`.
+pub type ProgressHandler = Box<FnMut() -> bool + Send>;
+/// The type of closure stored by `Connection::authorizer`.
+pub type Authorizer = Box<FnMut(AuthContext) -> Authorization + Send>;
+
+// Not defined by the bindings for SQLite versions prior to 3.8.3.
+const SQLITE_RECURSIVE: c_int = 33;
+
+/// The kind of change reported to an update hook.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+#[allow(non_camel_case_types)]
+pub enum Action {
+    /// A code that is not one of the known change operations.
+    UNKNOWN = -1,
+    /// A row was deleted.
+    SQLITE_DELETE = ffi::SQLITE_DELETE as isize,
+    /// A row was inserted.
+    SQLITE_INSERT = ffi::SQLITE_INSERT as isize,
+    /// A row was updated.
+    SQLITE_UPDATE = ffi::SQLITE_UPDATE as isize,
+}
+
+impl From<c_int> for Action {
+    fn from(code: c_int) -> Action {
+        match code {
+            ffi::SQLITE_DELETE => Action::SQLITE_DELETE,
+            ffi::SQLITE_INSERT => Action::SQLITE_INSERT,
+            ffi::SQLITE_UPDATE => Action::SQLITE_UPDATE,
+            _ => Action::UNKNOWN,
+        }
+    }
+}
+
+/// The operation that an authorizer is asked to allow, with its arguments.
+///
+/// See [Authorizer Action Codes](https://www.sqlite.org/c3ref/c_alter_table.html) for the
+/// meaning of each operation.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum AuthAction<'c> {
+    /// An operation that is not known to this version of rusqlite, or whose arguments could not
+    /// be decoded.
+    Unknown {
+        /// The SQLite action code.
+        code: i32,
+        /// The first argument of the operation.
+        arg1: Option<&'c str>,
+        /// The second argument of the operation.
+        arg2: Option<&'c str>,
+    },
+    /// `SQLITE_CREATE_INDEX`: create index `index_name` on table `table_name`.
+    CreateIndex { index_name: &'c str, table_name: &'c str },
+    /// `SQLITE_CREATE_TABLE`: create table `table_name`.
+    CreateTable { table_name: &'c str },
+    /// `SQLITE_CREATE_TEMP_INDEX`: create temporary index `index_name` on table `table_name`.
+    CreateTempIndex { index_name: &'c str, table_name: &'c str },
+    /// `SQLITE_CREATE_TEMP_TABLE`: create temporary table `table_name`.
+    CreateTempTable { table_name: &'c str },
+    /// `SQLITE_CREATE_TEMP_TRIGGER`: create temporary trigger `trigger_name` on table
+    /// `table_name`.
+    CreateTempTrigger { trigger_name: &'c str, table_name: &'c str },
+    /// `SQLITE_CREATE_TEMP_VIEW`: create temporary view `view_name`.
+    CreateTempView { view_name: &'c str },
+    /// `SQLITE_CREATE_TRIGGER`: create trigger `trigger_name` on table `table_name`.
+    CreateTrigger { trigger_name: &'c str, table_name: &'c str },
+    /// `SQLITE_CREATE_VIEW`: create view `view_name`.
+    CreateView { view_name: &'c str },
+    /// `SQLITE_DELETE`: delete rows from table `table_name`.
+    Delete { table_name: &'c str },
+    /// `SQLITE_DROP_INDEX`: drop index `index_name` of table `table_name`.
+    DropIndex { index_name: &'c str, table_name: &'c str },
+    /// `SQLITE_DROP_TABLE`: drop table `table_name`.
+    DropTable { table_name: &'c str },
+    /// `SQLITE_DROP_TEMP_INDEX`: drop temporary index `index_name` of table `table_name`.
+    DropTempIndex { index_name: &'c str, table_name: &'c str },
+    /// `SQLITE_DROP_TEMP_TABLE`: drop temporary table `table_name`.
+    DropTempTable { table_name: &'c str },
+    /// `SQLITE_DROP_TEMP_TRIGGER`: drop temporary trigger `trigger_name` of table `table_name`.
+    DropTempTrigger { trigger_name: &'c str, table_name: &'c str },
+    /// `SQLITE_DROP_TEMP_VIEW`: drop temporary view `view_name`.
+    DropTempView { view_name: &'c str },
+    /// `SQLITE_DROP_TRIGGER`: drop trigger `trigger_name` of table `table_name`.
+    DropTrigger { trigger_name: &'c str, table_name: &'c str },
+    /// `SQLITE_DROP_VIEW`: drop view `view_name`.
+    DropView { view_name: &'c str },
+    /// `SQLITE_INSERT`: insert rows into table `table_name`.
+    Insert { table_name: &'c str },
+    /// `SQLITE_PRAGMA`: run pragma `pragma_name`, with `pragma_value` if one is given.
+    Pragma { pragma_name: &'c str, pragma_value: Option<&'c str> },
+    /// `SQLITE_READ`: read column `column_name` of table `table_name`.
+    Read { table_name: &'c str, column_name: &'c str },
+    /// `SQLITE_SELECT`: run a `SELECT` statement.
+    Select,
+    /// `SQLITE_TRANSACTION`: `operation` is `BEGIN`, `COMMIT` or `ROLLBACK`.
+    Transaction { operation: &'c str },
+    /// `SQLITE_UPDATE`: update column `column_name` of table `table_name`.
+    Update { table_name: &'c str, column_name: &'c str },
+    /// `SQLITE_ATTACH`: `filename` is the file name as written in the `ATTACH` statement.
+    Attach { filename: &'c str },
+    /// `SQLITE_DETACH`: detach database `database_name`.
+    Detach { database_name: &'c str },
+    /// `SQLITE_ALTER_TABLE`: alter table `table_name` of database `database_name`.
+    AlterTable { database_name: &'c str, table_name: &'c str },
+    /// `SQLITE_REINDEX`: rebuild index `index_name`.
+    Reindex { index_name: &'c str },
+    /// `SQLITE_ANALYZE`: analyze table `table_name`.
+    Analyze { table_name: &'c str },
+    /// `SQLITE_CREATE_VTABLE`: create virtual table `table_name` using module `module_name`.
+    CreateVtable { table_name: &'c str, module_name: &'c str },
+    /// `SQLITE_DROP_VTABLE`: drop virtual table `table_name` of module `module_name`.
+    DropVtable { table_name: &'c str, module_name: &'c str },
+    /// `SQLITE_FUNCTION`: call SQL function `function_name`.
+    Function { function_name: &'c str },
+    /// `SQLITE_SAVEPOINT`: `operation` is `BEGIN`, `RELEASE` or `ROLLBACK`, applied to
+    /// savepoint `savepoint_name`.
+    Savepoint { operation: &'c str, savepoint_name: &'c str },
+    /// `SQLITE_RECURSIVE`: run a recursive common table expression.
+    Recursive,
+}
+
+impl<'c> AuthAction<'c> {
+    fn from_raw(code: c_int, arg1: Option<&'c str>, arg2: Option<&'c str>) -> AuthAction<'c> {
+        match (code, arg1, arg2) {
+            (ffi::SQLITE_CREATE_INDEX, Some(index_name), Some(table_name)) => {
+                AuthAction::CreateIndex {
+                    index_name: index_name,
+                    table_name: table_name,
+                }
+            }
+            (ffi::SQLITE_CREATE_TABLE, Some(table_name), _) => {
+                AuthAction::CreateTable { table_name: table_name }
+            }
+            (ffi::SQLITE_CREATE_TEMP_INDEX, Some(index_name), Some(table_name)) => {
+                AuthAction::CreateTempIndex {
+                    index_name: index_name,
+                    table_name: table_name,
+                }
+            }
+            (ffi::SQLITE_CREATE_TEMP_TABLE, Some(table_name), _) => {
+                AuthAction::CreateTempTable { table_name: table_name }
+            }
+            (ffi::SQLITE_CREATE_TEMP_TRIGGER, Some(trigger_name), Some(table_name)) => {
+                AuthAction::CreateTempTrigger {
+                    trigger_name: trigger_name,
+                    table_name: table_name,
+                }
+            }
+            (ffi::SQLITE_CREATE_TEMP_VIEW, Some(view_name), _) => {
+                AuthAction::CreateTempView { view_name: view_name }
+            }
+            (ffi::SQLITE_CREATE_TRIGGER, Some(trigger_name), Some(table_name)) => {
+                AuthAction::CreateTrigger {
+                    trigger_name: trigger_name,
+                    table_name: table_name,
+                }
+            }
+            (ffi::SQLITE_CREATE_VIEW, Some(view_name), _) => {
+                AuthAction::CreateView { view_name: view_name }
+            }
+            (ffi::SQLITE_DELETE, Some(table_name), _) => {
+                AuthAction::Delete { table_name: table_name }
+            }
+            (ffi::SQLITE_DROP_INDEX, Some(index_name), Some(table_name)) => {
+                AuthAction::DropIndex {
+                    index_name: index_name,
+                    table_name: table_name,
+                }
+            }
+            (ffi::SQLITE_DROP_TABLE, Some(table_name), _) => {
+                AuthAction::DropTable { table_name: table_name }
+            }
+            (ffi::SQLITE_DROP_TEMP_INDEX, Some(index_name), Some(table_name)) => {
+                AuthAction::DropTempIndex {
+                    index_name: index_name,
+                    table_name: table_name,
+                }
+            }
+            (ffi::SQLITE_DROP_TEMP_TABLE, Some(table_name), _) => {
+                AuthAction::DropTempTable { table_name: table_name }
+            }
+            (ffi::SQLITE_DROP_TEMP_TRIGGER, Some(trigger_name), Some(table_name)) => {
+                AuthAction::DropTempTrigger {
+                    trigger_name: trigger_name,
+                    table_name: table_name,
+                }
+            }
+            (ffi::SQLITE_DROP_TEMP_VIEW, Some(view_name), _) => {
+                AuthAction::DropTempView { view_name: view_name }
+            }
+            (ffi::SQLITE_DROP_TRIGGER, Some(trigger_name), Some(table_name)) => {
+                AuthAction::DropTrigger {
+                    trigger_name: trigger_name,
+                    table_name: table_name,
+                }
+            }
+            (ffi::SQLITE_DROP_VIEW, Some(view_name), _) => {
+                AuthAction::DropView { view_name: view_name }
+            }
+            (ffi::SQLITE_INSERT, Some(table_name), _) => {
+                AuthAction::Insert { table_name: table_name }
+            }
+            (ffi::SQLITE_PRAGMA, Some(pragma_name), pragma_value) => {
+                AuthAction::Pragma {
+                    pragma_name: pragma_name,
+                    pragma_value: pragma_value,
+                }
+            }
+            (ffi::SQLITE_READ, Some(table_name), Some(column_name)) => {
+                AuthAction::Read {
+                    table_name: table_name,
+                    column_name: column_name,
+                }
+            }
+            (ffi::SQLITE_SELECT, _, _) => AuthAction::Select,
+            (ffi::SQLITE_TRANSACTION, Some(operation), _) => {
+                AuthAction::Transaction { operation: operation }
+            }
+            (ffi::SQLITE_UPDATE, Some(table_name), Some(column_name)) => {
+                AuthAction::Update {
+                    table_name: table_name,
+                    column_name: column_name,
+                }
+            }
+            (ffi::SQLITE_ATTACH, Some(filename), _) => AuthAction::Attach { filename: filename },
+            (ffi::SQLITE_DETACH, Some(database_name), _) => {
+                AuthAction::Detach { database_name: database_name }
+            }
+            (ffi::SQLITE_ALTER_TABLE, Some(database_name), Some(table_name)) => {
+                AuthAction::AlterTable {
+                    database_name: database_name,
+                    table_name: table_name,
+                }
+            }
+            (ffi::SQLITE_REINDEX, Some(index_name), _) => {
+                AuthAction::Reindex { index_name: index_name }
+            }
+            (ffi::SQLITE_ANALYZE, Some(table_name), _) => {
+                AuthAction::Analyze { table_name: table_name }
+            }
+            (ffi::SQLITE_CREATE_VTABLE, Some(table_name), Some(module_name)) => {
+                AuthAction::CreateVtable {
+                    table_name: table_name,
+                    module_name: module_name,
+                }
+            }
+            (ffi::SQLITE_DROP_VTABLE, Some(table_name), Some(module_name)) => {
+                AuthAction::DropVtable {
+                    table_name: table_name,
+                    module_name: module_name,
+                }
+            }
+            (ffi::SQLITE_FUNCTION, _, Some(function_name)) => {
+                AuthAction::Function { function_name: function_name }
+            }
+            (ffi::SQLITE_SAVEPOINT, Some(operation), Some(savepoint_name)) => {
+                AuthAction::Savepoint {
+                    operation: operation,
+                    savepoint_name: savepoint_name,
+                }
+            }
+            (SQLITE_RECURSIVE, _, _) => AuthAction::Recursive,
+            (code, arg1, arg2) => {
+                AuthAction::Unknown {
+                    code: code,
+                    arg1: arg1,
+                    arg2: arg2,
+                }
+            }
+        }
+    }
+}
+
+/// The request passed to an authorizer.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct AuthContext<'c> {
+    /// The operation to authorize.
+    pub action: AuthAction<'c>,
+    /// The name of the database (e.g., `main` or `temp`) the operation applies to, if any.
+    pub database_name: Option<&'c str>,
+    /// The name of the innermost trigger or view that caused the operation, or `None` if it
+    /// comes directly from top-level SQL.
+    pub accessor: Option<&'c str>,
+}
+
+/// The answer of an authorizer.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Authorization {
+    /// Allow the operation.
+    Allow,
+    /// Fail the preparation of the statement with an authorization error.
+    Deny,
+    /// Allow the statement but skip the operation: reading a column yields `NULL`, and
+    /// deleting from a table that is being ignored does nothing. For other operations this is
+    /// the same as `Deny`.
+    Ignore,
+}
+
+impl Connection {
+    /// Register a callback to be invoked whenever a transaction is committed, replacing any
+    /// previous commit hook. Passing `None` removes the current hook.
+    ///
+    /// If the callback returns `true`, the commit is turned into a rollback.
+    ///
+    /// The callback must not modify the database connection that invoked it (e.g., by running
+    /// a statement on it). It is kept alive by the connection until it is replaced or the
+    /// connection is closed.
+    pub fn commit_hook<F>(&self, hook: Option<F>)
+        where F: FnMut() -> bool + Send + 'static
+    {
+        self.db.borrow_mut().commit_hook(hook.map(|f| Box::new(f) as CommitHook));
+    }
+
+    /// Register a callback to be invoked whenever a transaction is rolled back, replacing any
+    /// previous rollback hook. Passing `None` removes the current hook.
+    ///
+    /// The callback is not invoked when a transaction is rolled back because the connection is
+    /// closed. It must not modify the database connection that invoked it, and it is kept
+    /// alive by the connection until it is replaced or the connection is closed.
+    pub fn rollback_hook<F>(&self, hook: Option<F>)
+        where F: FnMut() + Send + 'static
+    {
+        self.db.borrow_mut().rollback_hook(hook.map(|f| Box::new(f) as RollbackHook));
+    }
+
+    /// Register a callback to be invoked whenever a row is inserted, updated or deleted in a
+    /// rowid table, replacing any previous update hook. Passing `None` removes the current
+    /// hook.
+    ///
+    /// The callback receives the kind of change, the name of the database (e.g., `main`), the
+    /// name of the table and the rowid of the affected row. It is not invoked for changes to
+    /// `WITHOUT ROWID` tables or internal system tables, nor for rows deleted by the truncate
+    /// optimization of a `DELETE` without a `WHERE` clause.
+    ///
+    /// The callback must not modify the database connection that invoked it, and it is kept
+    /// alive by the connection until it is replaced or the connection is closed.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::Connection;
+    /// # use rusqlite::hooks::Action;
+    /// fn log_changes(conn: &Connection) {
+    ///     conn.update_hook(Some(|action: Action, db: &str, table: &str, rowid: i64| {
+    ///         println!("{:?} {}.{} rowid {}", action, db, table, rowid);
+    ///     }));
+    /// }
+    /// ```
+    pub fn update_hook<F>(&self, hook: Option<F>)
+        where F: FnMut(Action, &str, &str, i64) + Send + 'static
+    {
+        self.db.borrow_mut().update_hook(hook.map(|f| Box::new(f) as UpdateHook));
+    }
+
+    /// Register a callback to be invoked periodically while a statement runs, about every
+    /// `num_ops` virtual machine instructions, replacing any previous progress handler.
+    /// Passing `None` (or a `num_ops` less than one) removes the current handler.
+    ///
+    /// If the callback returns `true`, the running operation is aborted and fails with
+    /// `SQLITE_INTERRUPT`. This can be used to report progress or to cancel long-running
+    /// queries; see also `Connection::get_interrupt_handle` to cancel them from another thread.
+    ///
+    /// The callback must not modify the database connection that invoked it, and it is kept
+    /// alive by the connection until it is replaced or the connection is closed.
+    pub fn progress_handler<F>(&self, num_ops: c_int, handler: Option<F>)
+        where F: FnMut() -> bool + Send + 'static
+    {
+        self.db
+            .borrow_mut()
+            .progress_handler(num_ops, handler.map(|f| Box::new(f) as ProgressHandler));
+    }
+
+    /// Register a callback to be invoked while statements are prepared, to allow or deny each
+    /// operation they would perform, replacing any previous authorizer. Passing `None` removes
+    /// the current authorizer.
+    ///
+    /// This can be used to run untrusted SQL safely, e.g., by denying `ATTACH` and all writes.
+    /// SQLite checks the operations when a statement is prepared (and when it is re-prepared
+    /// after a schema change), not when it runs, so a statement prepared before the authorizer
+    /// is set is not affected. Names that are not valid UTF-8 are passed as `None` (or as
+    /// `AuthAction::Unknown` for required names).
+    ///
+    /// The callback must not modify the database connection that invoked it, and it is kept
+    /// alive by the connection until it is replaced or the connection is closed.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::Connection;
+    /// # use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+    /// fn read_only(conn: &Connection) {
+    ///     conn.authorizer(Some(|ctx: AuthContext| match ctx.action {
+    ///         AuthAction::Select | AuthAction::Read { .. } | AuthAction::Function { .. } => {
+    ///             Authorization::Allow
+    ///         }
+    ///         _ => Authorization::Deny,
+    ///     }));
+    /// }
+    /// ```
+    pub fn authorizer<F>(&self, authorizer: Option<F>)
+        where F: for<'c> FnMut(AuthContext<'c>) -> Authorization + Send + 'static
+    {
+        self.db.borrow_mut().authorizer(authorizer.map(|f| Box::new(f) as Authorizer));
+    }
+}
+
+impl InnerConnection {
+    fn commit_hook(&mut self, hook: Option<CommitHook>) {
+        // The SQLite hook itself is registered when the connection is opened, so that it can
+        // also update the counters behind `transaction_stats`.
+        self.transaction_hooks.commit_hook = hook;
+    }
+
+    fn rollback_hook(&mut self, hook: Option<RollbackHook>) {
+        self.transaction_hooks.rollback_hook = hook;
+    }
+
+    fn update_hook(&mut self, hook: Option<UpdateHook>) {
+        unsafe extern "C" fn call_boxed_hook(p_arg: *mut c_void,
+                                             action: c_int,
+                                             db_name: *const c_char,
+                                             table_name: *const c_char,
+                                             row_id: ffi::sqlite3_int64) {
+            let hook: *mut UpdateHook = mem::transmute(p_arg);
+            let db_name = String::from_utf8_lossy(CStr::from_ptr(db_name).to_bytes());
+            let table_name = String::from_utf8_lossy(CStr::from_ptr(table_name).to_bytes());
+            (*hook)(Action::from(action), &db_name, &table_name, row_id);
+        }
+
+        let mut hook = hook.map(Box::new);
+        unsafe {
+            match hook {
+                Some(ref mut hook) => {
+                    let p_arg: *mut UpdateHook = &mut **hook;
+                    ffi::sqlite3_update_hook(self.db(), Some(call_boxed_hook), mem::transmute(p_arg))
+                }
+                None => ffi::sqlite3_update_hook(self.db(), None, ptr::null_mut()),
+            };
+        }
+        self.update_hook = hook;
+    }
+
+    fn progress_handler(&mut self, num_ops: c_int, handler: Option<ProgressHandler>) {
+        unsafe extern "C" fn call_boxed_handler(p_arg: *mut c_void) -> c_int {
+            let handler: *mut ProgressHandler = mem::transmute(p_arg);
+            if (*handler)() { 1 } else { 0 }
+        }
+
+        let mut handler = if num_ops > 0 { handler.map(Box::new) } else { None };
+        unsafe {
+            match handler {
+                Some(ref mut handler) => {
+                    let p_arg: *mut ProgressHandler = &mut **handler;
+                    ffi::sqlite3_progress_handler(self.db(),
+                                                  num_ops,
+                                                  Some(call_boxed_handler),
+                                                  mem::transmute(p_arg))
+                }
+                None => ffi::sqlite3_progress_handler(self.db(), 0, None, ptr::null_mut()),
+            }
+        }
+        self.progress_handler = handler;
+    }
+
+    fn authorizer(&mut self, authorizer: Option<Authorizer>) {
+        unsafe fn expect_str<'c>(s: *const c_char) -> Option<&'c str> {
+            if s.is_null() {
+                None
+            } else {
+                str::from_utf8(CStr::from_ptr(s).to_bytes()).ok()
+            }
+        }
+
+        unsafe extern "C" fn call_boxed_authorizer(p_arg: *mut c_void,
+                                                   action: c_int,
+                                                   arg1: *const c_char,
+                                                   arg2: *const c_char,
+                                                   db_name: *const c_char,
+                                                   accessor: *const c_char)
+                                                   -> c_int {
+            let authorizer: *mut Authorizer = mem::transmute(p_arg);
+            let ctx = AuthContext {
+                action: AuthAction::from_raw(action, expect_str(arg1), expect_str(arg2)),
+                database_name: expect_str(db_name),
+                accessor: expect_str(accessor),
+            };
+            match (*authorizer)(ctx) {
+                Authorization::Allow => ffi::SQLITE_OK,
+                Authorization::Deny => ffi::SQLITE_DENY,
+                Authorization::Ignore => ffi::SQLITE_IGNORE,
+            }
+        }
+
+        let mut authorizer = authorizer.map(Box::new);
+        unsafe {
+            match authorizer {
+                Some(ref mut authorizer) => {
+                    let p_arg: *mut Authorizer = &mut **authorizer;
+                    ffi::sqlite3_set_authorizer(self.db(),
+                                                Some(call_boxed_authorizer),
+                                                mem::transmute(p_arg))
+                }
+                None => ffi::sqlite3_set_authorizer(self.db(), None, ptr::null_mut()),
+            };
+        }
+        self.authorizer = authorizer;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use {Connection, Error, ErrorCode};
+    use super::{Action, AuthAction, AuthContext, Authorization};
+
+    #[test]
+    fn test_commit_hook() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (t TEXT)").unwrap();
+
+        let commits = Arc::new(AtomicUsize::new(0));
+        let hook_commits = commits.clone();
+        db.commit_hook(Some(move || {
+            hook_commits.fetch_add(1, Ordering::SeqCst);
+            false
+        }));
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES ('a'); COMMIT").unwrap();
+        assert_eq!(1, commits.load(Ordering::SeqCst));
+
+        db.commit_hook(None::<fn() -> bool>);
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES ('b'); COMMIT").unwrap();
+        assert_eq!(1, commits.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_commit_hook_veto() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (t TEXT)").unwrap();
+
+        db.commit_hook(Some(|| true));
+        assert!(db.execute_batch("BEGIN; INSERT INTO foo VALUES ('a'); COMMIT").is_err());
+        db.commit_hook(None::<fn() -> bool>);
+        assert_eq!((1, 1), db.transaction_stats());
+
+        let count: i64 = db.query_row("SELECT COUNT(*) FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!(0, count);
+    }
+
+    #[test]
+    fn test_rollback_hook() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (t TEXT)").unwrap();
+
+        let rollbacks = Arc::new(AtomicUsize::new(0));
+        let hook_rollbacks = rollbacks.clone();
+        db.rollback_hook(Some(move || {
+            hook_rollbacks.fetch_add(1, Ordering::SeqCst);
+        }));
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES ('a'); COMMIT").unwrap();
+        assert_eq!(0, rollbacks.load(Ordering::SeqCst));
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES ('b'); ROLLBACK").unwrap();
+        assert_eq!(1, rollbacks.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_update_hook() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (t TEXT)").unwrap();
+
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let hook_changes = changes.clone();
+        db.update_hook(Some(move |action, db: &str, table: &str, rowid| {
+            hook_changes.lock().unwrap().push((action, db.to_owned(), table.to_owned(), rowid));
+        }));
+        db.execute_batch("INSERT INTO foo VALUES ('a');
+                          UPDATE foo SET t = 'b' WHERE rowid = 1;
+                          DELETE FROM foo WHERE rowid = 1;")
+            .unwrap();
+
+        let main = "main".to_owned();
+        let foo = "foo".to_owned();
+        assert_eq!(vec![(Action::SQLITE_INSERT, main.clone(), foo.clone(), 1),
+                        (Action::SQLITE_UPDATE, main.clone(), foo.clone(), 1),
+                        (Action::SQLITE_DELETE, main.clone(), foo.clone(), 1)],
+                   *changes.lock().unwrap());
+
+        db.update_hook(None::<fn(Action, &str, &str, i64)>);
+        db.execute("INSERT INTO foo VALUES ('c')", &[]).unwrap();
+        assert_eq!(3, changes.lock().unwrap().len());
+    }
+
+    #[test]
+    fn test_progress_handler() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c LIMIT 100000)
+                   SELECT COUNT(*) FROM c";
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler_calls = calls.clone();
+        db.progress_handler(1000,
+                            Some(move || handler_calls.fetch_add(1, Ordering::SeqCst) >= 5));
+        match db.query_row(sql, &[], |r| r.get::<_, i64>(0)).unwrap_err() {
+            Error::SqliteFailure(err, _) => assert_eq!(ErrorCode::OperationInterrupted, err.code),
+            err => panic!("Unexpected error {}", err),
+        }
+        assert_eq!(6, calls.load(Ordering::SeqCst));
+
+        db.progress_handler(0, None::<fn() -> bool>);
+        assert_eq!(100000, db.query_row(sql, &[], |r| r.get::<_, i64>(0)).unwrap());
+        assert_eq!(6, calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_authorizer() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (x INTEGER, secret TEXT);
+                          INSERT INTO foo VALUES (1, 'hidden');")
+            .unwrap();
+
+        db.authorizer(Some(|ctx: AuthContext| match ctx.action {
+            AuthAction::Attach { .. } |
+            AuthAction::Insert { .. } => Authorization::Deny,
+            AuthAction::Read { column_name: "secret", .. } => Authorization::Ignore,
+            _ => Authorization::Allow,
+        }));
+
+        match db.execute_batch("ATTACH DATABASE ':memory:' AS other").unwrap_err() {
+            Error::SqliteFailure(err, _) => {
+                assert_eq!(ErrorCode::AuthorizationForStatementDenied, err.code)
+            }
+            err => panic!("Unexpected error {}", err),
+        }
+        assert!(db.execute("INSERT INTO foo VALUES (2, 'a')", &[]).is_err());
+        let (x, secret): (i64, Option<String>) =
+            db.query_row("SELECT x, secret FROM foo", &[], |r| (r.get(0), r.get(1))).unwrap();
+        assert_eq!(1, x);
+        assert_eq!(None, secret);
+
+        db.authorizer(None::<fn(AuthContext) -> Authorization>);
+        db.execute("INSERT INTO foo VALUES (2, 'a')", &[]).unwrap();
+    }
+
+    #[test]
+    fn test_auth_context() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (x INTEGER)").unwrap();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let authorizer_seen = seen.clone();
+        db.authorizer(Some(move |ctx: AuthContext| {
+            if let AuthAction::Update { table_name, column_name } = ctx.action {
+                authorizer_seen.lock()
+                    .unwrap()
+                    .push((table_name.to_owned(),
+                           column_name.to_owned(),
+                           ctx.database_name.map(str::to_owned)));
+            }
+            Authorization::Allow
+        }));
+        db.execute("UPDATE foo SET x = 1", &[]).unwrap();
+        assert_eq!(vec![("foo".to_owned(), "x".to_owned(), Some("main".to_owned()))],
+                   *seen.lock().unwrap());
+    }
+}
@@ -54,6 +54,8 @@
 
 extern crate libsqlite3_sys as ffi;
 extern crate lru_cache;
+#[cfg(feature = "csv")]
+extern crate csv;
 #[macro_use]
 extern crate bitflags;
 #[cfg(all(test, feature = "trace"))]
@@ -61,6 +63,7 @@ extern crate bitflags;
 extern crate lazy_static;
 
 use std::default::Default;
+use std::cmp;
 use std::convert;
 use std::mem;
 use std::ptr;
@@ -70,7 +73,9 @@ use std::cell::RefCell;
 use std::ffi::{CStr, CString};
 use std::result;
 use std::str;
-use std::sync::{Once, ONCE_INIT};
+use std::thread;
+use std::time::Duration;
+use std::sync::{Arc, Mutex, Once, ONCE_INIT};
 use std::sync::atomic::{AtomicBool, ATOMIC_BOOL_INIT, Ordering};
 use std::os::raw::{c_int, c_char};
 
@@ -79,7 +84,7 @@ use error::{error_from_sqlite_code, error_from_handle};
 use raw_statement::RawStatement;
 use cache::StatementCache;
 
-pub use statement::Statement;
+pub use statement::{Statement, UnknownParameters};
 use statement::StatementCrateImpl;
 
 pub use row::{Row, Rows, MappedRows, AndThenRows, RowIndex};
@@ -91,10 +96,13 @@ pub use transaction::{DropBehavior, Savepoint, Transaction, TransactionBehavior}
 
 #[allow(deprecated)]
 pub use error::SqliteError;
-pub use error::Error;
+pub use error::{ConstraintKind, Error, ErrorCategory};
 pub use ffi::ErrorCode;
 
 pub use cache::CachedStatement;
+pub use pragma::{CheckpointMode, CheckpointResult, DbSize};
+pub use schema::{IndexDef, Stat1Row, TableDiff};
+pub use settings::Settings;
 pub use version::*;
 
 #[cfg(feature = "load_extension")]
@@ -104,7 +112,9 @@ pub use load_extension_guard::{SqliteLoadExtensionGuard, LoadExtensionGuard};
 pub mod types;
 mod version;
 mod transaction;
+mod busy;
 mod cache;
+pub mod config;
 mod error;
 mod raw_statement;
 mod row;
@@ -119,8 +129,21 @@ pub mod backup;
 pub mod functions;
 #[cfg(feature = "blob")]
 pub mod blob;
+#[cfg(feature = "collation")]
+mod collation;
 #[cfg(feature = "limits")]
 pub mod limits;
+#[cfg(feature = "serialize")]
+mod serialize;
+#[cfg(feature = "hooks")]
+pub mod hooks;
+#[cfg(feature = "csv")]
+mod csv_io;
+#[cfg(feature = "vfs")]
+pub mod vfs;
+mod pragma;
+mod schema;
+mod settings;
 
 // Number of cached prepared statements we'll hold on to.
 const STATEMENT_CACHE_DEFAULT_CAPACITY: usize = 16;
@@ -141,11 +164,84 @@ fn str_to_cstring(s: &str) -> Result<CString> {
     Ok(try!(CString::new(s)))
 }
 
+// Quote `name` for use as an SQL identifier (e.g., a table or column name).
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace("\"", "\"\""))
+}
+
 fn path_to_cstring(p: &Path) -> Result<CString> {
     let s = try!(p.to_str().ok_or_else(|| Error::InvalidPath(p.to_owned())));
     str_to_cstring(s)
 }
 
+// Strip trailing semicolons, comments and a trailing top-level ORDER BY clause from `sql` so
+// that it can be embedded as a subquery. Quoted strings, identifiers, comments and parenthesized
+// expressions are skipped over when looking for the `ORDER BY` keywords.
+fn strip_for_subquery(sql: &str) -> &str {
+    let bytes = sql.as_bytes();
+
+    let mut words: Vec<(usize, &str)> = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+    // The end of the last token that is not a comment, whitespace or a semicolon.
+    let mut end = 0;
+    while i < bytes.len() {
+        let close = match bytes[i] {
+            b'\'' => Some(b'\''),
+            b'"' => Some(b'"'),
+            b'`' => Some(b'`'),
+            b'[' => Some(b']'),
+            _ => None,
+        };
+        if let Some(close) = close {
+            i += 1;
+            while i < bytes.len() && bytes[i] != close {
+                i += 1;
+            }
+            i = cmp::min(i + 1, bytes.len());
+            end = i;
+        } else if bytes[i..].starts_with(b"--") {
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+        } else if bytes[i..].starts_with(b"/*") {
+            i = sql[i + 2..].find("*/").map_or(bytes.len(), |end| i + 2 + end + 2);
+        } else if bytes[i] == b'(' {
+            depth += 1;
+            i += 1;
+            end = i;
+        } else if bytes[i] == b')' {
+            depth -= 1;
+            i += 1;
+            end = i;
+        } else if bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            if depth == 0 {
+                words.push((start, &sql[start..i]));
+            }
+            end = i;
+        } else if bytes[i] == b';' || bytes[i].is_ascii_whitespace() {
+            i += 1;
+        } else {
+            i += 1;
+            end = i;
+        }
+    }
+    let sql = &sql[..end];
+
+    let order_by = words.windows(2)
+        .rposition(|w| w[0].1.eq_ignore_ascii_case("order") && w[1].1.eq_ignore_ascii_case("by"));
+    match order_by {
+        Some(pos) if !words[pos..].iter().any(|w| w.1.eq_ignore_ascii_case("limit")) => {
+            sql[..words[pos].0].trim_right()
+        }
+        _ => sql,
+    }
+}
+
 /// Name for a database within a SQLite connection.
 pub enum DatabaseName<'a> {
     /// The main database.
@@ -158,9 +254,9 @@ pub enum DatabaseName<'a> {
     Attached(&'a str),
 }
 
-// Currently DatabaseName is only used by the backup and blob mods, so hide this (private)
-// impl to avoid dead code warnings.
-#[cfg(any(feature = "backup", feature = "blob"))]
+// Currently DatabaseName is only used by the backup, blob and serialize mods, so hide this
+// (private) impl to avoid dead code warnings.
+#[cfg(any(feature = "backup", feature = "blob", feature = "serialize"))]
 impl<'a> DatabaseName<'a> {
     fn to_cstring(&self) -> Result<CString> {
         use self::DatabaseName::{Main, Temp, Attached};
@@ -227,13 +323,27 @@ impl Connection {
     /// underlying SQLite open call fails.
     pub fn open_with_flags<P: AsRef<Path>>(path: P, flags: OpenFlags) -> Result<Connection> {
         let c_path = try!(path_to_cstring(path.as_ref()));
-        InnerConnection::open_with_flags(&c_path, flags).map(|db| {
-                                                                 Connection {
-                db: RefCell::new(db),
-                cache: StatementCache::with_capacity(STATEMENT_CACHE_DEFAULT_CAPACITY),
-                path: Some(path.as_ref().to_path_buf()),
-            }
-                                                             })
+        Connection::open_inner(&c_path, flags, None, Some(path.as_ref().to_path_buf()))
+    }
+
+    /// Open a new connection to a SQLite database using the
+    /// [VFS](https://www.sqlite.org/vfs.html) named `vfs` (e.g., `"unix-excl"` or
+    /// `"memdb"`) instead of the default one.
+    ///
+    /// [Database Connection](http://www.sqlite.org/c3ref/open.html) for a description of valid
+    /// flag combinations.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `path` or `vfs` cannot be converted to a C-compatible string, if no
+    /// VFS named `vfs` is registered, or if the underlying SQLite open call fails.
+    pub fn open_with_flags_and_vfs<P: AsRef<Path>>(path: P,
+                                                   flags: OpenFlags,
+                                                   vfs: &str)
+                                                   -> Result<Connection> {
+        let c_path = try!(path_to_cstring(path.as_ref()));
+        let c_vfs = try!(str_to_cstring(vfs));
+        Connection::open_inner(&c_path, flags, Some(&c_vfs), Some(path.as_ref().to_path_buf()))
     }
 
     /// Open a new connection to an in-memory SQLite database.
@@ -246,13 +356,36 @@ impl Connection {
     /// Will return `Err` if the underlying SQLite open call fails.
     pub fn open_in_memory_with_flags(flags: OpenFlags) -> Result<Connection> {
         let c_memory = try!(str_to_cstring(":memory:"));
-        InnerConnection::open_with_flags(&c_memory, flags).map(|db| {
-                                                                   Connection {
+        Connection::open_inner(&c_memory, flags, None, None)
+    }
+
+    /// Open a new connection to an in-memory SQLite database using the VFS named `vfs`.
+    ///
+    /// [Database Connection](http://www.sqlite.org/c3ref/open.html) for a description of valid
+    /// flag combinations.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `vfs` cannot be converted to a C-compatible string, if no VFS named
+    /// `vfs` is registered, or if the underlying SQLite open call fails.
+    pub fn open_in_memory_with_flags_and_vfs(flags: OpenFlags, vfs: &str) -> Result<Connection> {
+        let c_memory = try!(str_to_cstring(":memory:"));
+        let c_vfs = try!(str_to_cstring(vfs));
+        Connection::open_inner(&c_memory, flags, Some(&c_vfs), None)
+    }
+
+    fn open_inner(c_path: &CString,
+                  flags: OpenFlags,
+                  vfs: Option<&CString>,
+                  path: Option<PathBuf>)
+                  -> Result<Connection> {
+        InnerConnection::open_with_flags(c_path, flags, vfs).map(|db| {
+            Connection {
                 db: RefCell::new(db),
                 cache: StatementCache::with_capacity(STATEMENT_CACHE_DEFAULT_CAPACITY),
-                path: None,
+                path: path,
             }
-                                                               })
+        })
     }
 
     /// Convenience method to run multiple SQL statements (that cannot take any parameters).
@@ -301,8 +434,10 @@ impl Connection {
     /// Will return `Err` if `sql` cannot be converted to a C-compatible string or if the
     /// underlying SQLite call fails.
     pub fn execute(&self, sql: &str, params: &[&ToSql]) -> Result<c_int> {
-        self.prepare(sql)
-            .and_then(|mut stmt| stmt.execute(params))
+        self.retry_if_busy(|| {
+                               self.prepare(sql)
+                                   .and_then(|mut stmt| stmt.execute(params))
+                           })
     }
 
     /// Convenience method to prepare and execute a single SQL statement with named parameter(s).
@@ -324,8 +459,39 @@ impl Connection {
     /// Will return `Err` if `sql` cannot be converted to a C-compatible string or if the
     /// underlying SQLite call fails.
     pub fn execute_named(&self, sql: &str, params: &[(&str, &ToSql)]) -> Result<c_int> {
-        self.prepare(sql)
-            .and_then(|mut stmt| stmt.execute_named(params))
+        self.retry_if_busy(|| {
+                               self.prepare(sql)
+                                   .and_then(|mut stmt| stmt.execute_named(params))
+                           })
+    }
+
+    /// Convenience method to prepare and execute a single INSERT, UPDATE or DELETE statement
+    /// with a `RETURNING` clause, mapping `f` over the returned rows.
+    ///
+    /// See `Statement::execute_returning`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn insert(conn: &Connection) -> Result<Vec<i64>> {
+    ///     conn.execute_returning("INSERT INTO test (name) VALUES ('one'), ('two') RETURNING id",
+    ///                            &[],
+    ///                            |row| row.get_checked(0))
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string, if the
+    /// underlying SQLite call fails or if `f` returns an error.
+    pub fn execute_returning<T, F>(&self, sql: &str, params: &[&ToSql], mut f: F) -> Result<Vec<T>>
+        where F: FnMut(&Row) -> Result<T>
+    {
+        self.retry_if_busy(|| {
+                               self.prepare(sql)
+                                   .and_then(|mut stmt| stmt.execute_returning(params, &mut f))
+                           })
     }
 
     /// Get the SQLite rowid of the most recent successful INSERT.
@@ -358,8 +524,12 @@ impl Connection {
     pub fn query_row<T, F>(&self, sql: &str, params: &[&ToSql], f: F) -> Result<T>
         where F: FnOnce(&Row) -> T
     {
-        let mut stmt = try!(self.prepare(sql));
-        stmt.query_row(params, f)
+        // `f` is only called once a row has been read, after which nothing is retried.
+        let mut f = Some(f);
+        self.retry_if_busy(|| {
+                               let mut stmt = try!(self.prepare(sql));
+                               stmt.query_row(params, |row| (f.take().unwrap())(row))
+                           })
     }
 
     /// Convenience method to execute a query with named parameter(s) that is expected to return
@@ -374,10 +544,13 @@ impl Connection {
     pub fn query_row_named<T, F>(&self, sql: &str, params: &[(&str, &ToSql)], f: F) -> Result<T>
         where F: FnOnce(&Row) -> T
     {
-        let mut stmt = try!(self.prepare(sql));
-        let mut rows = try!(stmt.query_named(params));
+        let mut f = Some(f);
+        self.retry_if_busy(|| {
+                               let mut stmt = try!(self.prepare(sql));
+                               let mut rows = try!(stmt.query_named(params));
 
-        rows.get_expected_row().map(|r| f(&r))
+                               rows.get_expected_row().map(|r| (f.take().unwrap())(&r))
+                           })
     }
 
     /// Convenience method to execute a query that is expected to return a single row,
@@ -411,12 +584,91 @@ impl Connection {
         where F: FnOnce(&Row) -> result::Result<T, E>,
               E: convert::From<Error>
     {
-        let mut stmt = try!(self.prepare(sql));
-        let mut rows = try!(stmt.query(params));
+        let mut f = Some(f);
+        let result = self.retry_if_busy(|| {
+            let mut stmt = try!(self.prepare(sql));
+            let mut rows = try!(stmt.query(params));
+
+            rows.get_expected_row().map(|r| (f.take().unwrap())(&r))
+        });
+        result.map_err(E::from).and_then(|r| r)
+    }
+
+    // Run `f`, calling it again as configured by `set_auto_retry` while it fails because the
+    // database is busy or locked and no transaction is open.
+    fn retry_if_busy<T, F>(&self, mut f: F) -> Result<T>
+        where F: FnMut() -> Result<T>
+    {
+        let policy = self.db.borrow().auto_retry;
+        let (max_attempts, backoff) = match policy {
+            Some(policy) => policy,
+            None => return f(),
+        };
+        let mut attempts = 1;
+        loop {
+            let result = f();
+            let busy = match result {
+                Err(Error::SqliteFailure(ref err, _)) => {
+                    err.code == ErrorCode::DatabaseBusy || err.code == ErrorCode::DatabaseLocked
+                }
+                _ => false,
+            };
+            if !busy || attempts >= max_attempts || !self.is_autocommit() {
+                return result;
+            }
+            thread::sleep(backoff);
+            attempts += 1;
+        }
+    }
 
-        rows.get_expected_row()
-            .map_err(E::from)
-            .and_then(|r| f(&r))
+    /// Convenience method to count the rows returned by a SELECT statement.
+    ///
+    /// The query is run as `SELECT count(*) FROM (<base_select>)` with the same `params`. Any
+    /// trailing semicolons are removed from `base_select`, as is a trailing `ORDER BY` clause
+    /// (unless it is followed by a `LIMIT`, which depends on the ordering).
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Result,Connection};
+    /// fn count_adults(conn: &Connection) -> Result<i64> {
+    ///     conn.count_query("SELECT * FROM people WHERE age >= ? ORDER BY name", &[&18])
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `base_select` cannot be converted to a C-compatible string or if the
+    /// underlying SQLite call fails.
+    pub fn count_query(&self, base_select: &str, params: &[&ToSql]) -> Result<i64> {
+        let sql = format!("SELECT count(*) FROM ({})", strip_for_subquery(base_select));
+        self.query_row(&sql, params, |row| row.get_checked(0))
+            .and_then(|count| count)
+    }
+
+    /// Convenience method to check whether a SELECT statement returns any rows.
+    ///
+    /// The query is run as `SELECT EXISTS(<sql>)` with the same `params`, so SQLite stops as
+    /// soon as it finds the first row. Trailing semicolons and a trailing `ORDER BY` clause are
+    /// removed from `sql` as for `count_query`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Result,Connection};
+    /// fn has_admin(conn: &Connection) -> Result<bool> {
+    ///     conn.exists("SELECT 1 FROM users WHERE role = ?", &[&"admin"])
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string or if the
+    /// underlying SQLite call fails.
+    pub fn exists(&self, sql: &str, params: &[&ToSql]) -> Result<bool> {
+        let sql = format!("SELECT EXISTS({})", strip_for_subquery(sql));
+        self.query_row(&sql, params, |row| row.get_checked(0))
+            .and_then(|exists| exists)
     }
 
     /// Convenience method to execute a query that is expected to return a single row.
@@ -565,8 +817,16 @@ impl Connection {
         self.db.borrow_mut().decode_result(code)
     }
 
-    fn changes(&self) -> c_int {
-        self.db.borrow_mut().changes()
+    /// Get the number of rows modified, inserted or deleted by the most recently completed
+    /// INSERT, UPDATE or DELETE statement (including one run by `execute_batch`). Changes
+    /// made by triggers and foreign key actions are not counted.
+    ///
+    /// Uses [sqlite3_changes](https://www.sqlite.org/c3ref/changes.html) under the hood. SQLite
+    /// keeps this counter as a 32-bit `int`, so counts above `i32::max_value()` are not
+    /// reported correctly; `sqlite3_changes64`, which fixes this, requires SQLite 3.37.0 and is
+    /// not part of the bundled SQLite or the pregenerated bindings.
+    pub fn changes(&self) -> u64 {
+        self.db.borrow_mut().changes() as u64
     }
 
     /// Test for auto-commit mode.
@@ -579,6 +839,89 @@ impl Connection {
     pub fn is_busy(&self) -> bool {
         self.db.borrow().is_busy()
     }
+
+    /// Get the number of prepared statements on this connection that have not been finalized
+    /// yet, including statements held by the prepared statement cache (see
+    /// `flush_prepared_statement_cache`).
+    ///
+    /// This is mostly useful in tests, to check that no statements are leaked before the
+    /// connection is closed.
+    pub fn open_statement_count(&self) -> usize {
+        self.db.borrow().open_statement_count()
+    }
+
+    /// Change the schema name of the main database from `"main"` to `name`, so that its tables
+    /// are referenced as `name.table` in queries (e.g., across attached databases).
+    ///
+    /// Uses [SQLITE_DBCONFIG_MAINDBNAME](https://www.sqlite.org/c3ref/c_dbconfig_enable_fkey.html)
+    /// under the hood, which requires SQLite 3.15.0 or later.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn open_tenant(tenant: &str) -> Result<Connection> {
+    ///     let conn = try!(Connection::open(format!("{}.db3", tenant)));
+    ///     try!(conn.set_main_db_name(tenant));
+    ///     Ok(conn)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `name` cannot be converted to a C-compatible string, if the linked
+    /// SQLite is older than 3.15.0, or if it is called too late: any statement that is still
+    /// prepared on this connection (including statements in the prepared statement cache) has
+    /// been compiled against the old name, so this must be called before preparing statements.
+    pub fn set_main_db_name(&self, name: &str) -> Result<()> {
+        self.db.borrow_mut().set_main_db_name(name)
+    }
+
+    /// Get an `InterruptHandle` that can be used to interrupt the operations running on this
+    /// connection from another thread.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use std::thread;
+    /// # use std::time::Duration;
+    /// fn run_with_deadline(conn: &Connection) -> Result<()> {
+    ///     let handle = conn.get_interrupt_handle();
+    ///     thread::spawn(move || {
+    ///         thread::sleep(Duration::from_secs(10));
+    ///         handle.interrupt();
+    ///     });
+    ///     // Fails with `SQLITE_INTERRUPT` if it takes more than 10 seconds.
+    ///     conn.execute_batch("DELETE FROM huge_table")
+    /// }
+    /// ```
+    pub fn get_interrupt_handle(&self) -> InterruptHandle {
+        self.db.borrow().get_interrupt_handle()
+    }
+}
+
+/// Allows interrupting the operations running on a connection, from any thread.
+///
+/// The handle can outlive its connection; interrupting a closed connection does nothing.
+pub struct InterruptHandle {
+    db_lock: Arc<Mutex<*mut ffi::sqlite3>>,
+}
+
+unsafe impl Send for InterruptHandle {}
+unsafe impl Sync for InterruptHandle {}
+
+impl InterruptHandle {
+    /// Interrupt the operation currently running on the connection, if any, which then fails
+    /// with `SQLITE_INTERRUPT`. If the operation is part of an explicit transaction, the
+    /// transaction may be rolled back. Statements started after the operation has been
+    /// interrupted are not affected.
+    pub fn interrupt(&self) {
+        let db_handle = self.db_lock.lock().unwrap();
+        if !db_handle.is_null() {
+            unsafe { ffi::sqlite3_interrupt(*db_handle) }
+        }
+    }
 }
 
 impl fmt::Debug for Connection {
@@ -591,6 +934,35 @@ impl fmt::Debug for Connection {
 
 struct InnerConnection {
     db: *mut ffi::sqlite3,
+    // SQLite does not copy the name passed to SQLITE_DBCONFIG_MAINDBNAME, so we have to keep it
+    // alive for as long as the connection is open.
+    main_db_name: Option<CString>,
+    // The closure registered with `sqlite3_busy_handler`, if any; it must outlive the
+    // registration.
+    busy_handler: Option<Box<busy::BusyHandler>>,
+    #[cfg(feature = "hooks")]
+    update_hook: Option<Box<hooks::UpdateHook>>,
+    #[cfg(feature = "hooks")]
+    authorizer: Option<Box<hooks::Authorizer>>,
+    #[cfg(feature = "hooks")]
+    progress_handler: Option<Box<hooks::ProgressHandler>>,
+    #[cfg(feature = "trace")]
+    trace_callbacks: Box<TraceCallbacks>,
+    // The maximum number of attempts and the delay between them set by `set_auto_retry`.
+    auto_retry: Option<(u32, Duration)>,
+    // The counters behind `transaction_stats` and the commit and rollback hooks; registered
+    // with SQLite when the connection is opened.
+    transaction_hooks: Box<transaction::TransactionHooks>,
+    // Shared with every `InterruptHandle`; reset to null when the connection is closed.
+    interrupt_lock: Arc<Mutex<*mut ffi::sqlite3>>,
+}
+
+// The closures registered by `Connection::trace` and `Connection::profile`. They are boxed
+// together because `sqlite3_trace_v2` takes a single callback for both.
+#[cfg(feature = "trace")]
+struct TraceCallbacks {
+    tracer: Option<trace::Tracer>,
+    profiler: Option<trace::Profiler>,
 }
 
 /// Old name for `OpenFlags`. `SqliteOpenFlags` is deprecated.
@@ -749,7 +1121,10 @@ To fix this, either:
 }
 
 impl InnerConnection {
-    fn open_with_flags(c_path: &CString, flags: OpenFlags) -> Result<InnerConnection> {
+    fn open_with_flags(c_path: &CString,
+                       flags: OpenFlags,
+                       vfs: Option<&CString>)
+                       -> Result<InnerConnection> {
         ensure_valid_sqlite_version();
         ensure_safe_sqlite_threading_mode()?;
 
@@ -761,10 +1136,25 @@ impl InnerConnection {
         if (1 << (flags.bits & 0x7)) & 0x46 == 0 {
             return Err(Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_MISUSE), None));
         }
+        // SQLite silently picks one of these when both are given; reject them instead.
+        for &(a, b) in &[(OpenFlags::SQLITE_OPEN_NO_MUTEX, OpenFlags::SQLITE_OPEN_FULL_MUTEX),
+                         (OpenFlags::SQLITE_OPEN_SHARED_CACHE,
+                          OpenFlags::SQLITE_OPEN_PRIVATE_CACHE)] {
+            if flags.contains(a | b) {
+                return Err(Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_MISUSE),
+                                                Some(format!("{:?} and {:?} are mutually \
+                                                              exclusive",
+                                                             a,
+                                                             b))));
+            }
+        }
 
         unsafe {
             let mut db: *mut ffi::sqlite3 = mem::uninitialized();
-            let r = ffi::sqlite3_open_v2(c_path.as_ptr(), &mut db, flags.bits(), ptr::null());
+            let r = ffi::sqlite3_open_v2(c_path.as_ptr(),
+                                         &mut db,
+                                         flags.bits(),
+                                         vfs.map_or(ptr::null(), |vfs| vfs.as_ptr()));
             if r != ffi::SQLITE_OK {
                 let e = if db.is_null() {
                     error_from_sqlite_code(r, None)
@@ -786,7 +1176,25 @@ impl InnerConnection {
             // attempt to turn on extended results code; don't fail if we can't.
             ffi::sqlite3_extended_result_codes(db, 1);
 
-            Ok(InnerConnection { db: db })
+            Ok(InnerConnection {
+                   db: db,
+                   main_db_name: None,
+                   busy_handler: None,
+                   #[cfg(feature = "hooks")]
+                   update_hook: None,
+                   #[cfg(feature = "hooks")]
+                   authorizer: None,
+                   #[cfg(feature = "hooks")]
+                   progress_handler: None,
+                   #[cfg(feature = "trace")]
+                   trace_callbacks: Box::new(TraceCallbacks {
+                                                 tracer: None,
+                                                 profiler: None,
+                                             }),
+                   auto_retry: None,
+                   transaction_hooks: transaction::TransactionHooks::register(db),
+                   interrupt_lock: Arc::new(Mutex::new(db)),
+               })
         }
     }
 
@@ -803,16 +1211,24 @@ impl InnerConnection {
     }
 
     fn close(&mut self) -> Result<()> {
+        // Hold the lock while closing so no `InterruptHandle` can use the handle concurrently.
+        let interrupt_lock = self.interrupt_lock.clone();
+        let mut shared_handle = interrupt_lock.lock().unwrap();
         unsafe {
             let r = ffi::sqlite3_close(self.db());
             let r = self.decode_result(r);
             if r.is_ok() {
+                *shared_handle = ptr::null_mut();
                 self.db = ptr::null_mut();
             }
             r
         }
     }
 
+    fn get_interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle { db_lock: self.interrupt_lock.clone() }
+    }
+
     fn execute_batch(&mut self, sql: &str) -> Result<()> {
         let c_sql = try!(str_to_cstring(sql));
         unsafe {
@@ -885,6 +1301,43 @@ impl InnerConnection {
         unsafe { ffi::sqlite3_get_autocommit(self.db()) != 0 }
     }
 
+    fn open_statement_count(&self) -> usize {
+        let db = self.db();
+        let mut count = 0;
+        unsafe {
+            let mut stmt = ffi::sqlite3_next_stmt(db, ptr::null_mut());
+            while !stmt.is_null() {
+                count += 1;
+                stmt = ffi::sqlite3_next_stmt(db, stmt);
+            }
+        }
+        count
+    }
+
+    fn set_main_db_name(&mut self, name: &str) -> Result<()> {
+        const SQLITE_DBCONFIG_MAINDBNAME: c_int = 1000;
+
+        let misuse = |msg: &str| {
+            Err(Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_MISUSE), Some(msg.to_owned())))
+        };
+        if version_number() < 3_015_000 {
+            return misuse("SQLITE_DBCONFIG_MAINDBNAME requires SQLite 3.15.0 or later");
+        }
+        if self.open_statement_count() != 0 {
+            return misuse("the main database name must be set before any statement is prepared");
+        }
+
+        let c_name = try!(str_to_cstring(name));
+        let r = unsafe {
+            ffi::sqlite3_db_config(self.db(), SQLITE_DBCONFIG_MAINDBNAME, c_name.as_ptr())
+        };
+        if r != ffi::SQLITE_OK {
+            return Err(error_from_sqlite_code(r, None));
+        }
+        self.main_db_name = Some(c_name);
+        Ok(())
+    }
+
     fn is_busy(&self) -> bool {
         let db = self.db();
         unsafe {
@@ -950,6 +1403,106 @@ mod test {
         Connection::open_in_memory().unwrap()
     }
 
+    #[test]
+    fn test_count_query() {
+        let db = checked_memory_handle();
+        db.execute_batch("CREATE TABLE foo(x INTEGER, y TEXT)").unwrap();
+        for i in 0..10 {
+            db.execute("INSERT INTO foo VALUES (?, 'a;b')", &[&i]).unwrap();
+        }
+
+        let sql = "SELECT x, y FROM foo WHERE x >= ? AND y = 'a;b' ORDER BY x DESC;  ";
+        let count = db.count_query(sql, &[&4]).unwrap();
+        let mut stmt = db.prepare(sql).unwrap();
+        let rows = stmt.query_map(&[&4], |row| row.get::<_, i32>(0)).unwrap();
+        assert_eq!(6, count);
+        assert_eq!(count as usize, rows.count());
+
+        let limited = "SELECT x FROM foo ORDER BY x LIMIT 3";
+        assert_eq!(3, db.count_query(limited, &[]).unwrap());
+
+        let commented = "SELECT x FROM foo WHERE x < 2 -- note";
+        assert_eq!(2, db.count_query(commented, &[]).unwrap());
+    }
+
+    #[test]
+    fn test_exists() {
+        let db = checked_memory_handle();
+        db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES (1), (2);")
+            .unwrap();
+
+        assert!(db.exists("SELECT * FROM foo WHERE x = ?", &[&2]).unwrap());
+        assert!(!db.exists("SELECT * FROM foo WHERE x = ?;", &[&3]).unwrap());
+        assert!(db.exists("SELECT 1 FROM foo -- c", &[]).unwrap());
+    }
+
+    #[test]
+    fn test_strip_for_subquery() {
+        assert_eq!("SELECT 1", strip_for_subquery("SELECT 1;;\n"));
+        assert_eq!("SELECT x FROM foo", strip_for_subquery("SELECT x FROM foo order  By x"));
+        assert_eq!("SELECT x FROM foo WHERE y = ' ORDER BY '",
+                   strip_for_subquery("SELECT x FROM foo WHERE y = ' ORDER BY '"));
+        assert_eq!("SELECT x FROM (SELECT x FROM foo ORDER BY x)",
+                   strip_for_subquery("SELECT x FROM (SELECT x FROM foo ORDER BY x)"));
+        assert_eq!("SELECT x FROM foo ORDER BY x LIMIT 5",
+                   strip_for_subquery("SELECT x FROM foo ORDER BY x LIMIT 5;"));
+        assert_eq!("SELECT x FROM foo",
+                   strip_for_subquery("SELECT x FROM foo -- note"));
+        assert_eq!("SELECT x FROM foo",
+                   strip_for_subquery("SELECT x FROM foo ORDER BY x; /* note */ -- ;\n"));
+        assert_eq!("SELECT x -- note\nFROM foo",
+                   strip_for_subquery("SELECT x -- note\nFROM foo"));
+    }
+
+    #[test]
+    fn test_open_statement_count() {
+        let db = checked_memory_handle();
+        assert_eq!(0, db.open_statement_count());
+
+        {
+            let _stmt1 = db.prepare("SELECT 1").unwrap();
+            let _stmt2 = db.prepare("SELECT 2").unwrap();
+            assert_eq!(2, db.open_statement_count());
+        }
+        assert_eq!(0, db.open_statement_count());
+
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        db.execute("INSERT INTO foo VALUES (?)", &[&1]).unwrap();
+        assert_eq!(0, db.open_statement_count());
+
+        db.prepare_cached("SELECT x FROM foo").unwrap();
+        assert_eq!(1, db.open_statement_count());
+        db.flush_prepared_statement_cache();
+        assert_eq!(0, db.open_statement_count());
+    }
+
+    #[test]
+    fn test_set_main_db_name() {
+        let db = checked_memory_handle();
+        db.set_main_db_name("tenant").unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES (42);").unwrap();
+
+        let x: i32 = db.query_row("SELECT x FROM tenant.foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!(42, x);
+
+        // A second name is accepted as long as no statement is live.
+        db.set_main_db_name("other").unwrap();
+        let x: i32 = db.query_row("SELECT x FROM other.foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!(42, x);
+    }
+
+    #[test]
+    fn test_set_main_db_name_too_late() {
+        let db = checked_memory_handle();
+        let stmt = db.prepare("SELECT 1").unwrap();
+        match db.set_main_db_name("tenant").unwrap_err() {
+            Error::SqliteFailure(err, Some(_)) => assert_eq!(ErrorCode::APIMisuse, err.code),
+            err => panic!("Unexpected error {}", err),
+        }
+        drop(stmt);
+        db.set_main_db_name("tenant").unwrap();
+    }
+
     #[test]
     #[cfg_attr(rustfmt, rustfmt_skip)]
     fn test_persistence() {
@@ -1021,11 +1574,54 @@ mod test {
     fn test_open_with_flags() {
         for bad_flags in &[OpenFlags::empty(),
                            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_READ_WRITE,
-                           OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_CREATE] {
+                           OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_CREATE,
+                           OpenFlags::default() | OpenFlags::SQLITE_OPEN_FULL_MUTEX,
+                           OpenFlags::default() | OpenFlags::SQLITE_OPEN_SHARED_CACHE |
+                           OpenFlags::SQLITE_OPEN_PRIVATE_CACHE] {
             assert!(Connection::open_in_memory_with_flags(*bad_flags).is_err());
         }
     }
 
+    #[test]
+    fn test_open_read_only_uri() {
+        let temp_dir = TempDir::new("test_open_read_only_uri").unwrap();
+        let path = temp_dir.path().join("test.db3");
+        {
+            let db = Connection::open(&path).unwrap();
+            db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES (1);").unwrap();
+        }
+
+        let uri = format!("file:{}?mode=ro", path.to_str().unwrap());
+        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_URI;
+        let db = Connection::open_with_flags(&uri, flags).unwrap();
+        let x: i64 = db.query_row("SELECT x FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!(1, x);
+        match db.execute("INSERT INTO foo VALUES (2)", &[]).unwrap_err() {
+            Error::SqliteFailure(err, _) => assert_eq!(ErrorCode::ReadOnly, err.code),
+            err => panic!("Unexpected error {}", err),
+        }
+
+        let db = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+        assert!(db.execute("INSERT INTO foo VALUES (2)", &[]).is_err());
+    }
+
+    #[test]
+    fn test_open_with_vfs() {
+        use std::ffi::CStr;
+
+        let default_vfs = unsafe {
+            let vfs = ffi::sqlite3_vfs_find(ptr::null());
+            CStr::from_ptr((*vfs).zName).to_str().unwrap().to_owned()
+        };
+        let db = Connection::open_in_memory_with_flags_and_vfs(OpenFlags::default(), &default_vfs)
+            .unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+
+        assert!(Connection::open_in_memory_with_flags_and_vfs(OpenFlags::default(),
+                                                               "no-such-vfs")
+                        .is_err());
+    }
+
     #[test]
     #[cfg_attr(rustfmt, rustfmt_skip)]
     fn test_execute_batch() {
@@ -1215,6 +1811,24 @@ mod test {
         assert_eq!(db.last_insert_rowid(), 10);
     }
 
+    #[test]
+    fn test_changes() {
+        let db = checked_memory_handle();
+        db.execute_batch("CREATE TABLE foo(x INTEGER);
+                          INSERT INTO foo VALUES (1);
+                          INSERT INTO foo VALUES (2);
+                          INSERT INTO foo VALUES (3);")
+            .unwrap();
+        assert_eq!(1, db.changes());
+
+        db.execute_batch("UPDATE foo SET x = x + 1 WHERE x > 1").unwrap();
+        assert_eq!(2, db.changes());
+
+        // Statements that are not INSERT, UPDATE or DELETE leave the count unchanged.
+        db.query_row("SELECT COUNT(*) FROM foo", &[], |r| r.get::<_, i64>(0)).unwrap();
+        assert_eq!(2, db.changes());
+    }
+
     #[test]
     fn test_is_autocommit() {
         let db = checked_memory_handle();
@@ -1238,6 +1852,43 @@ mod test {
         assert!(!db.is_busy());
     }
 
+    #[test]
+    fn test_interrupt() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::thread;
+        use std::time::Duration;
+
+        let db = checked_memory_handle();
+        let handle = db.get_interrupt_handle();
+        let done = Arc::new(AtomicBool::new(false));
+        let interrupter_done = done.clone();
+        let interrupter = thread::spawn(move || while !interrupter_done.load(Ordering::SeqCst) {
+                                            thread::sleep(Duration::from_millis(10));
+                                            handle.interrupt();
+                                        });
+
+        let result: Result<i64> = db.query_row("WITH RECURSIVE c(x) AS
+                                                  (SELECT 1 UNION ALL SELECT x + 1 FROM c)
+                                                SELECT COUNT(*) FROM c",
+                                               &[],
+                                               |r| r.get(0));
+        done.store(true, Ordering::SeqCst);
+        interrupter.join().unwrap();
+        match result.unwrap_err() {
+            Error::SqliteFailure(err, _) => {
+                assert_eq!(err.code, ErrorCode::OperationInterrupted);
+            }
+            err => panic!("Unexpected error {}", err),
+        }
+
+        // The connection is still usable, and interrupting it once closed does nothing.
+        assert_eq!(1, db.query_row("SELECT 1", &[], |r| r.get::<_, i64>(0)).unwrap());
+        let handle = db.get_interrupt_handle();
+        db.close().unwrap();
+        handle.interrupt();
+    }
+
     #[test]
     fn test_statement_debugging() {
         let db = checked_memory_handle();
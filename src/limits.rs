@@ -19,6 +19,15 @@ impl Connection {
         let c = self.db.borrow_mut();
         unsafe { ffi::sqlite3_limit(c.db(), limit as c_int, new_val) }
     }
+
+    /// Returns the maximum number of host parameters (e.g., `?`) allowed in a single statement
+    /// on this connection, i.e., the current value of `SQLITE_LIMIT_VARIABLE_NUMBER`.
+    ///
+    /// Helpers that bind many values (e.g., a large `IN (...)` list) can use this to split the
+    /// values into chunks.
+    pub fn max_variable_number(&self) -> i32 {
+        self.limit(Limit::SQLITE_LIMIT_VARIABLE_NUMBER)
+    }
 }
 
 #[cfg(test)]
@@ -68,4 +77,13 @@ mod test {
             assert_eq!(2, db.limit(Limit::SQLITE_LIMIT_WORKER_THREADS));
         }
     }
+
+    #[test]
+    fn test_max_variable_number() {
+        let db = Connection::open_in_memory().unwrap();
+        assert!(db.max_variable_number() > 0);
+
+        db.set_limit(Limit::SQLITE_LIMIT_VARIABLE_NUMBER, 10);
+        assert_eq!(10, db.max_variable_number());
+    }
 }
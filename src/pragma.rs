@@ -0,0 +1,450 @@
+//! Helpers for running `PRAGMA`s, for `PRAGMA`s that report on or control the database file,
+//! and WAL checkpoints.
+use std::os::raw::c_int;
+use std::ptr;
+
+use ffi;
+
+use {Connection, DatabaseName, Error, Result, Row, quote_identifier};
+use types::{FromSql, ToSql, ToSqlOutput, ValueRef};
+
+// Not defined by the bindings for SQLite versions prior to 3.8.8.
+const SQLITE_CHECKPOINT_TRUNCATE: c_int = 3;
+
+/// The size of a database, as reported by `Connection::database_size`.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub struct DbSize {
+    /// The total number of pages in the database file (`PRAGMA page_count`).
+    pub page_count: i64,
+    /// The number of unused pages in the database file (`PRAGMA freelist_count`).
+    pub freelist_count: i64,
+    /// The size of a page, in bytes (`PRAGMA page_size`).
+    pub page_size: i64,
+}
+
+impl DbSize {
+    /// The size of the database file, in bytes.
+    pub fn total_bytes(&self) -> i64 {
+        self.page_count * self.page_size
+    }
+
+    /// The number of bytes in pages that are in use.
+    pub fn used_bytes(&self) -> i64 {
+        (self.page_count - self.freelist_count) * self.page_size
+    }
+
+    /// The number of bytes in unused pages, which `VACUUM` would reclaim.
+    pub fn free_bytes(&self) -> i64 {
+        self.freelist_count * self.page_size
+    }
+}
+
+/// How much work `Connection::wal_checkpoint` does; see
+/// [sqlite3_wal_checkpoint_v2](https://www.sqlite.org/c3ref/wal_checkpoint_v2.html).
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum CheckpointMode {
+    /// Checkpoint as many frames as possible without waiting for readers or writers.
+    Passive,
+    /// Wait for writers to finish, then checkpoint every frame, waiting for readers as needed.
+    Full,
+    /// Like `Full`, then also wait for readers so the next writer restarts the log from the
+    /// beginning.
+    Restart,
+    /// Like `Restart`, then also truncate the log file to zero bytes. Requires SQLite 3.8.8
+    /// or later.
+    Truncate,
+}
+
+/// The outcome of `Connection::wal_checkpoint`.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub struct CheckpointResult {
+    /// The number of frames in the write-ahead log, or -1 if the database is not in WAL mode.
+    pub log_frames: i32,
+    /// The number of frames of the log that have been checkpointed into the database, or -1 if
+    /// the database is not in WAL mode.
+    pub checkpointed_frames: i32,
+}
+
+fn misuse(msg: String) -> Error {
+    Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_MISUSE), Some(msg))
+}
+
+// Build `PRAGMA [schema.]name[ = value]`. PRAGMAs cannot have bound parameters, so `value` is
+// written out as a literal.
+fn pragma_sql(schema: Option<DatabaseName>, name: &str, value: Option<&ToSql>) -> Result<String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(misuse(format!("invalid pragma name: {:?}", name)));
+    }
+    let mut sql = String::from("PRAGMA ");
+    match schema {
+        Some(DatabaseName::Main) => sql.push_str("main."),
+        Some(DatabaseName::Temp) => sql.push_str("temp."),
+        Some(DatabaseName::Attached(s)) => {
+            sql.push_str(&quote_identifier(s));
+            sql.push('.');
+        }
+        None => {}
+    }
+    sql.push_str(name);
+    if let Some(value) = value {
+        let value = try!(value.to_sql());
+        let value = match value {
+            ToSqlOutput::Borrowed(v) => v,
+            ToSqlOutput::Owned(ref v) => ValueRef::from(v),
+            #[cfg(feature = "blob")]
+            ToSqlOutput::ZeroBlob(_) => ValueRef::Null,
+        };
+        sql.push_str(" = ");
+        match value {
+            ValueRef::Integer(i) => sql.push_str(&i.to_string()),
+            ValueRef::Real(r) => sql.push_str(&format!("{:?}", r)),
+            ValueRef::Text(s) => {
+                sql.push('\'');
+                sql.push_str(&s.replace("'", "''"));
+                sql.push('\'');
+            }
+            ValueRef::Null | ValueRef::Blob(_) => {
+                return Err(misuse(format!("pragma {} can only be set to an integer, real or \
+                                           text value",
+                                          name)));
+            }
+        }
+    }
+    Ok(sql)
+}
+
+impl Connection {
+    /// Run `PRAGMA [schema.]pragma_name` and return the first column of its first row, e.g.,
+    /// `conn.pragma_query_value::<i64>(None, "page_size")`. If `schema` is `None`, the pragma
+    /// applies to the main database or to all of them, depending on the pragma.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `pragma_name` is not a valid pragma name, if the pragma returns no
+    /// rows (which SQLite does for unknown pragmas), or if the value cannot be converted to `T`.
+    pub fn pragma_query_value<T: FromSql>(&self,
+                                          schema: Option<DatabaseName>,
+                                          pragma_name: &str)
+                                          -> Result<T> {
+        let sql = try!(pragma_sql(schema, pragma_name, None));
+        self.query_row_and_then(&sql, &[], |row| row.get_checked(0))
+    }
+
+    /// Run `PRAGMA [schema.]pragma_name`, calling `f` for each row it returns, e.g., for
+    /// `PRAGMA database_list` or `PRAGMA compile_options`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `pragma_name` is not a valid pragma name, if the underlying SQLite
+    /// call fails, or if `f` returns an error.
+    pub fn pragma_query<F>(&self,
+                           schema: Option<DatabaseName>,
+                           pragma_name: &str,
+                           mut f: F)
+                           -> Result<()>
+        where F: FnMut(&Row) -> Result<()>
+    {
+        let sql = try!(pragma_sql(schema, pragma_name, None));
+        let mut stmt = try!(self.prepare(&sql));
+        let mut rows = try!(stmt.query(&[]));
+        while let Some(row) = rows.next() {
+            try!(f(&try!(row)));
+        }
+        Ok(())
+    }
+
+    /// Run `PRAGMA [schema.]pragma_name = value`, e.g.,
+    /// `conn.pragma_update(None, "cache_size", &-4096)`. Text values are quoted, so they cannot
+    /// inject SQL. Any rows the pragma returns are ignored; use `pragma_update_and_check` to
+    /// read them.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `pragma_name` is not a valid pragma name, if `value` is NULL or a
+    /// BLOB, or if the underlying SQLite call fails.
+    pub fn pragma_update(&self,
+                         schema: Option<DatabaseName>,
+                         pragma_name: &str,
+                         value: &ToSql)
+                         -> Result<()> {
+        let sql = try!(pragma_sql(schema, pragma_name, Some(value)));
+        let mut stmt = try!(self.prepare(&sql));
+        let mut rows = try!(stmt.query(&[]));
+        while let Some(row) = rows.next() {
+            try!(row);
+        }
+        Ok(())
+    }
+
+    /// Run `PRAGMA [schema.]pragma_name = value` like `pragma_update`, and return the result of
+    /// calling `f` on the first row it returns, e.g., the new mode for `journal_mode`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `pragma_name` is not a valid pragma name, if `value` is NULL or a
+    /// BLOB, if the pragma returns no rows, or if the underlying SQLite call fails.
+    pub fn pragma_update_and_check<F, T>(&self,
+                                         schema: Option<DatabaseName>,
+                                         pragma_name: &str,
+                                         value: &ToSql,
+                                         f: F)
+                                         -> Result<T>
+        where F: FnOnce(&Row) -> Result<T>
+    {
+        let sql = try!(pragma_sql(schema, pragma_name, Some(value)));
+        self.query_row_and_then(&sql, &[], f)
+    }
+
+    /// Get the main database's `user_version`, an integer that applications can use to track
+    /// their schema version.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn user_version(&self) -> Result<i32> {
+        self.pragma_query_value(None, "user_version")
+    }
+
+    /// Set the main database's `user_version`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn set_user_version(&self, version: i32) -> Result<()> {
+        self.pragma_update(None, "user_version", &version)
+    }
+
+    /// Return whether foreign key constraints are enforced on this connection.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn foreign_keys(&self) -> Result<bool> {
+        self.pragma_query_value(None, "foreign_keys")
+    }
+
+    /// Turn enforcement of foreign key constraints on or off. This has no effect inside a
+    /// transaction.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn set_foreign_keys(&self, enabled: bool) -> Result<()> {
+        self.pragma_update(None, "foreign_keys", &enabled)
+    }
+
+    /// Get the journal mode of the main database, in lower case (e.g., `"delete"` or `"wal"`).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn journal_mode(&self) -> Result<String> {
+        self.pragma_query_value(None, "journal_mode")
+    }
+
+    /// Ask SQLite to change the journal mode of the main database to `mode` (e.g., `"wal"`),
+    /// returning the resulting mode in lower case. SQLite does not fail if the mode cannot be
+    /// changed (e.g., to `"wal"` for an in-memory database); the current mode is returned
+    /// instead.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn set_journal_mode(&self, mode: &str) -> Result<String> {
+        self.pragma_update_and_check(None, "journal_mode", &mode, |row| row.get_checked(0))
+    }
+
+    /// Switch the main database to write-ahead logging (`PRAGMA journal_mode = WAL`).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the journal mode could not be changed (e.g., for an in-memory
+    /// database, or inside a transaction), or if the underlying SQLite call fails.
+    pub fn enable_wal_mode(&self) -> Result<()> {
+        let mode: String = try!(self.query_row("PRAGMA journal_mode = WAL", &[], |r| r.get(0)));
+        if mode.eq_ignore_ascii_case("wal") {
+            Ok(())
+        } else {
+            let msg = format!("could not change the journal mode from {} to wal", mode);
+            Err(Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_MISUSE), Some(msg)))
+        }
+    }
+
+    /// Copy the content of the write-ahead log of every attached database into the database
+    /// files, so the log does not keep growing. SQLite does this automatically when the log
+    /// reaches 1000 pages by default; this gives long-running writers control over it.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with `SQLITE_BUSY` if a `Full`, `Restart` or `Truncate` checkpoint
+    /// could not complete because another connection was using the database, or if the
+    /// underlying SQLite call fails.
+    pub fn wal_checkpoint(&self, mode: CheckpointMode) -> Result<CheckpointResult> {
+        let mode = match mode {
+            CheckpointMode::Passive => ffi::SQLITE_CHECKPOINT_PASSIVE,
+            CheckpointMode::Full => ffi::SQLITE_CHECKPOINT_FULL,
+            CheckpointMode::Restart => ffi::SQLITE_CHECKPOINT_RESTART,
+            CheckpointMode::Truncate => SQLITE_CHECKPOINT_TRUNCATE,
+        };
+        let mut log_frames = 0;
+        let mut checkpointed_frames = 0;
+        let mut c = self.db.borrow_mut();
+        let r = unsafe {
+            ffi::sqlite3_wal_checkpoint_v2(c.db(),
+                                           ptr::null(),
+                                           mode,
+                                           &mut log_frames,
+                                           &mut checkpointed_frames)
+        };
+        try!(c.decode_result(r));
+        Ok(CheckpointResult {
+               log_frames: log_frames,
+               checkpointed_frames: checkpointed_frames,
+           })
+    }
+
+    /// Get the size of the main database, e.g., to decide when to `VACUUM` it.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn database_size(&self) -> Result<DbSize> {
+        Ok(DbSize {
+               page_count: try!(self.query_row("PRAGMA page_count", &[], |r| r.get(0))),
+               freelist_count: try!(self.query_row("PRAGMA freelist_count", &[], |r| r.get(0))),
+               page_size: try!(self.query_row("PRAGMA page_size", &[], |r| r.get(0))),
+           })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate tempdir;
+
+    use self::tempdir::TempDir;
+    use {Connection, DatabaseName, Error, ErrorCode};
+    use super::CheckpointMode;
+
+    #[test]
+    fn test_pragma_query_value() {
+        let db = Connection::open_in_memory().unwrap();
+        let page_size: i64 = db.pragma_query_value(None, "page_size").unwrap();
+        assert!(page_size > 0);
+        let temp_page_size: i64 = db.pragma_query_value(Some(DatabaseName::Temp), "page_size")
+            .unwrap();
+        assert!(temp_page_size > 0);
+        assert!(db.pragma_query_value::<i64>(None, "no_such_pragma").is_err());
+    }
+
+    #[test]
+    fn test_pragma_query() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("ATTACH DATABASE ':memory:' AS \"my db\"").unwrap();
+        let mut names = Vec::new();
+        db.pragma_query(None, "database_list", |row| {
+                names.push(try!(row.get_checked::<_, String>(1)));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(vec!["main", "my db"], names);
+
+        let page_size: i64 = db.pragma_query_value(Some(DatabaseName::Attached("my db")),
+                                                    "page_size")
+            .unwrap();
+        assert!(page_size > 0);
+    }
+
+    #[test]
+    fn test_pragma_update() {
+        let db = Connection::open_in_memory().unwrap();
+        db.pragma_update(None, "cache_size", &-4096).unwrap();
+        assert_eq!(-4096, db.pragma_query_value::<i64>(None, "cache_size").unwrap());
+
+        db.pragma_update(Some(DatabaseName::Main), "user_version", &"1; DROP TABLE x").unwrap();
+        assert_eq!(1, db.user_version().unwrap());
+
+        match db.pragma_update(None, "user_version = 2; DROP TABLE x; --", &3).unwrap_err() {
+            Error::SqliteFailure(err, _) => assert_eq!(ErrorCode::APIMisuse, err.code),
+            err => panic!("Unexpected error {}", err),
+        }
+        assert!(db.pragma_update(None, "user_version", &None::<i32>).is_err());
+        assert_eq!(1, db.user_version().unwrap());
+    }
+
+    #[test]
+    fn test_typed_pragmas() {
+        let db = Connection::open_in_memory().unwrap();
+        db.set_user_version(42).unwrap();
+        assert_eq!(42, db.user_version().unwrap());
+
+        db.set_foreign_keys(true).unwrap();
+        assert!(db.foreign_keys().unwrap());
+        db.set_foreign_keys(false).unwrap();
+        assert!(!db.foreign_keys().unwrap());
+
+        assert_eq!("memory", db.journal_mode().unwrap());
+        assert_eq!("off", db.set_journal_mode("OFF").unwrap());
+        assert_eq!("off", db.journal_mode().unwrap());
+        // In-memory databases cannot use WAL; the mode is left unchanged.
+        assert_eq!("off", db.set_journal_mode("wal").unwrap());
+    }
+
+    #[test]
+    fn test_database_size() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (b BLOB)").unwrap();
+        {
+            let mut stmt = db.prepare("INSERT INTO foo VALUES (zeroblob(1000))").unwrap();
+            for _ in 0..100 {
+                stmt.execute(&[]).unwrap();
+            }
+        }
+
+        let full = db.database_size().unwrap();
+        assert!(full.page_count > 0);
+        assert_eq!(0, full.freelist_count);
+        assert_eq!(full.total_bytes(), full.used_bytes());
+
+        db.execute("DELETE FROM foo", &[]).unwrap();
+        let deleted = db.database_size().unwrap();
+        assert_eq!(full.page_count, deleted.page_count);
+        assert!(deleted.freelist_count > 0);
+        assert_eq!(deleted.total_bytes(), deleted.used_bytes() + deleted.free_bytes());
+
+        db.execute_batch("VACUUM").unwrap();
+        let vacuumed = db.database_size().unwrap();
+        assert_eq!(0, vacuumed.freelist_count);
+        assert!(vacuumed.page_count < deleted.page_count);
+    }
+
+    #[test]
+    fn test_wal_checkpoint() {
+        let temp_dir = TempDir::new("test_wal_checkpoint").unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = Connection::open(&path).unwrap();
+
+        let result = db.wal_checkpoint(CheckpointMode::Passive).unwrap();
+        assert_eq!((-1, -1), (result.log_frames, result.checkpointed_frames));
+
+        db.enable_wal_mode().unwrap();
+        db.execute_batch("PRAGMA wal_autocheckpoint = 0;
+                          CREATE TABLE foo (b BLOB);
+                          INSERT INTO foo VALUES (zeroblob(10000));")
+            .unwrap();
+
+        let result = db.wal_checkpoint(CheckpointMode::Passive).unwrap();
+        assert!(result.log_frames > 0);
+        assert_eq!(result.log_frames, result.checkpointed_frames);
+
+        let result = db.wal_checkpoint(CheckpointMode::Truncate).unwrap();
+        assert_eq!((0, 0), (result.log_frames, result.checkpointed_frames));
+        let wal_len = ::std::fs::metadata(temp_dir.path().join("test.db3-wal")).unwrap().len();
+        assert_eq!(0, wal_len);
+    }
+
+    #[test]
+    fn test_enable_wal_mode_in_memory() {
+        let db = Connection::open_in_memory().unwrap();
+        assert!(db.enable_wal_mode().is_err());
+    }
+}
@@ -36,6 +36,14 @@ impl RawStatement {
         unsafe { ffi::sqlite3_reset(self.0) }
     }
 
+    pub fn is_busy(&self) -> bool {
+        unsafe { ffi::sqlite3_stmt_busy(self.0) != 0 }
+    }
+
+    pub fn readonly(&self) -> bool {
+        unsafe { ffi::sqlite3_stmt_readonly(self.0) != 0 }
+    }
+
     pub fn bind_parameter_count(&self) -> c_int {
         unsafe { ffi::sqlite3_bind_parameter_count(self.0) }
     }
@@ -0,0 +1,485 @@
+//! Helpers for inspecting and comparing the tables of a database.
+use {Connection, Error, Result};
+use quote_identifier;
+use types::Value;
+
+/// The differences between two tables, as computed by `Connection::diff_tables`.
+///
+/// Rows are given as the values of `columns`, in order.
+#[derive(Clone,Debug,PartialEq)]
+pub struct TableDiff {
+    /// The columns of the compared tables.
+    pub columns: Vec<String>,
+
+    /// Rows of the first table whose key does not appear in the second table.
+    pub only_in_a: Vec<Vec<Value>>,
+
+    /// Rows of the second table whose key does not appear in the first table.
+    pub only_in_b: Vec<Vec<Value>>,
+
+    /// Rows whose key appears in both tables but whose other values differ, as pairs of the
+    /// row in the first table and the row in the second table.
+    pub changed: Vec<(Vec<Value>, Vec<Value>)>,
+}
+
+/// The definition of an index, as returned by `Connection::indexes`.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct IndexDef {
+    /// The name of the index.
+    pub name: String,
+
+    /// Whether the index is a UNIQUE index.
+    pub unique: bool,
+
+    /// Whether the index is a partial index (i.e., it has a WHERE clause).
+    pub partial: bool,
+
+    /// The indexed columns, in index order. Columns of an index on an expression are `None`.
+    pub columns: Vec<Option<String>>,
+}
+
+/// A row of the `sqlite_stat1` table written by `ANALYZE`, as returned by `Connection::stat1`.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Stat1Row {
+    /// The name of the analyzed table.
+    pub table: String,
+
+    /// The name of the analyzed index, or `None` for the row that only records the number of
+    /// rows of a table without indexes.
+    pub index: Option<String>,
+
+    /// The statistics themselves: the approximate number of rows in the table or index,
+    /// followed (for an index) by the average number of rows matched by each prefix of the
+    /// indexed columns, and possibly flags such as `unordered`.
+    pub stat: String,
+}
+
+impl Stat1Row {
+    /// The approximate number of rows in the table or index (the first number of `stat`).
+    pub fn row_count(&self) -> Option<i64> {
+        self.stat.split_whitespace().next().and_then(|n| n.parse().ok())
+    }
+}
+
+fn column_list(alias: &str, columns: &[String]) -> String {
+    let columns: Vec<String> = columns.iter()
+        .map(|c| format!("{}.{}", alias, quote_identifier(c)))
+        .collect();
+    columns.join(", ")
+}
+
+fn query_values(conn: &Connection, sql: &str) -> Result<Vec<Vec<Value>>> {
+    let mut stmt = try!(conn.prepare(sql));
+    stmt.query_matrix(&[]).map(|(_, rows)| rows)
+}
+
+impl Connection {
+    /// Compare the rows of `table_a` and `table_b`, matching rows on the values of
+    /// `key_columns`.
+    ///
+    /// `table_b` must have all of the columns of `table_a`. If `key_columns` is empty, rows are
+    /// matched on all of their columns, so no row is ever reported as changed. The key should
+    /// be unique in both tables; NULL keys match each other. Rows are returned ordered by key.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn count_changes(conn: &Connection) -> Result<usize> {
+    ///     let diff = try!(conn.diff_tables("imported", "live", &["id"]));
+    ///     Ok(diff.only_in_a.len() + diff.only_in_b.len() + diff.changed.len())
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if one of the tables does not exist or does not have the required
+    /// columns, if a key column is not a column of `table_a`, or if the underlying SQLite call
+    /// fails.
+    pub fn diff_tables(&self,
+                       table_a: &str,
+                       table_b: &str,
+                       key_columns: &[&str])
+                       -> Result<TableDiff> {
+        let table_a = quote_identifier(table_a);
+        let table_b = quote_identifier(table_b);
+        let columns: Vec<String> = {
+            let stmt = try!(self.prepare(&format!("SELECT * FROM {}", table_a)));
+            stmt.column_names().into_iter().map(String::from).collect()
+        };
+
+        let keys: Vec<String> = if key_columns.is_empty() {
+            columns.clone()
+        } else {
+            let mut keys = Vec::with_capacity(key_columns.len());
+            for key in key_columns {
+                if !columns.iter().any(|c| c == key) {
+                    return Err(Error::InvalidColumnName(key.to_string()));
+                }
+                keys.push(key.to_string());
+            }
+            keys
+        };
+        let key_match: Vec<String> = keys.iter()
+            .map(|k| format!("a.{0} IS b.{0}", quote_identifier(k)))
+            .collect();
+        let key_match = key_match.join(" AND ");
+        let columns_a = column_list("a", &columns);
+        let columns_b = column_list("b", &columns);
+
+        let only_in_a = try!(query_values(self,
+                                          &format!("SELECT {} FROM {} AS a WHERE NOT EXISTS \
+                                                    (SELECT 1 FROM {} AS b WHERE {}) \
+                                                    ORDER BY {}",
+                                                   columns_a,
+                                                   table_a,
+                                                   table_b,
+                                                   key_match,
+                                                   column_list("a", &keys))));
+        let only_in_b = try!(query_values(self,
+                                          &format!("SELECT {} FROM {} AS b WHERE NOT EXISTS \
+                                                    (SELECT 1 FROM {} AS a WHERE {}) \
+                                                    ORDER BY {}",
+                                                   columns_b,
+                                                   table_b,
+                                                   table_a,
+                                                   key_match,
+                                                   column_list("b", &keys))));
+        let changed = try!(query_values(self,
+                                        &format!("SELECT {0}, {1} FROM {2} AS a JOIN {3} AS b \
+                                                  ON {4} WHERE EXISTS (SELECT {0} EXCEPT \
+                                                  SELECT {1}) ORDER BY {5}",
+                                                 columns_a,
+                                                 columns_b,
+                                                 table_a,
+                                                 table_b,
+                                                 key_match,
+                                                 column_list("a", &keys))));
+        let changed = changed.into_iter()
+            .map(|mut row| {
+                let b = row.split_off(columns.len());
+                (row, b)
+            })
+            .collect();
+
+        Ok(TableDiff {
+               columns: columns,
+               only_in_a: only_in_a,
+               only_in_b: only_in_b,
+               changed: changed,
+           })
+    }
+
+    /// Get the indexes of `table`, using
+    /// [`PRAGMA index_list` and `PRAGMA index_info`](https://www.sqlite.org/pragma.html#pragma_index_list).
+    ///
+    /// This includes the indexes SQLite creates automatically for UNIQUE and PRIMARY KEY
+    /// constraints. Detecting partial indexes requires SQLite 3.8.9 or later; with older
+    /// versions, `partial` is always `false`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn indexes(&self, table: &str) -> Result<Vec<IndexDef>> {
+        let mut list = try!(self.prepare(&format!("PRAGMA index_list({})",
+                                                  quote_identifier(table))));
+        let partial = list.column_index("partial").ok();
+        let mut indexes = try!(list.query_map_into(&[], |row| {
+            Ok(IndexDef {
+                   name: try!(row.get_checked("name")),
+                   unique: try!(row.get_checked("unique")),
+                   partial: match partial {
+                       Some(i) => try!(row.get_checked(i)),
+                       None => false,
+                   },
+                   columns: Vec::new(),
+               })
+        }));
+
+        for index in &mut indexes {
+            let mut info = try!(self.prepare(&format!("PRAGMA index_info({})",
+                                                      quote_identifier(&index.name))));
+            let mut columns: Vec<(i32, Option<String>)> =
+                try!(info.query_map_into(&[], |row| {
+                    Ok((try!(row.get_checked("seqno")), try!(row.get_checked("name"))))
+                }));
+            columns.sort_by_key(|&(seqno, _)| seqno);
+            index.columns = columns.into_iter().map(|(_, name)| name).collect();
+        }
+        Ok(indexes)
+    }
+
+    /// Run `ANALYZE` on `table`, or on every table of every attached database if `table` is
+    /// `None`, so that the query planner can choose indexes based on the statistics it
+    /// collects. Use `stat1` to inspect the collected statistics.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `table` does not exist or if the underlying SQLite call fails.
+    pub fn analyze(&self, table: Option<&str>) -> Result<()> {
+        match table {
+            Some(table) => self.execute_batch(&format!("ANALYZE {}", quote_identifier(table))),
+            None => self.execute_batch("ANALYZE"),
+        }
+    }
+
+    /// Read the statistics collected by `ANALYZE` for the main database from its
+    /// `sqlite_stat1` table, ordered by table and index name. Returns an empty list if the
+    /// database has never been analyzed.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn stat1(&self) -> Result<Vec<Stat1Row>> {
+        let exists: bool = try!(self.query_row("SELECT COUNT(*) > 0 FROM sqlite_master \
+                                                WHERE type = 'table' AND name = 'sqlite_stat1'",
+                                               &[],
+                                               |r| r.get(0)));
+        if !exists {
+            return Ok(Vec::new());
+        }
+        let mut stmt = try!(self.prepare("SELECT tbl, idx, stat FROM sqlite_stat1 \
+                                          ORDER BY tbl, idx"));
+        stmt.query_map_into(&[], |row| {
+            Ok(Stat1Row {
+                   table: try!(row.get_checked(0)),
+                   index: try!(row.get_checked(1)),
+                   stat: try!(row.get_checked(2)),
+               })
+        })
+    }
+
+    /// Drop all of the tables of the main database, except SQLite's internal `sqlite_*`
+    /// tables.
+    ///
+    /// Virtual tables are dropped first, together with their shadow tables (e.g., the
+    /// `_data` and `_idx` tables of an FTS5 table). The tables are dropped inside a savepoint,
+    /// so if one cannot be dropped, none of them are. Foreign key enforcement is disabled while
+    /// the tables are dropped, so they can be dropped in any order, and is restored afterwards.
+    /// Note that SQLite ignores changes to `PRAGMA foreign_keys` inside a transaction, so this
+    /// should be called outside of one if foreign keys are enabled.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn drop_all_tables(&self) -> Result<()> {
+        let foreign_keys: bool = try!(self.query_row("PRAGMA foreign_keys", &[], |r| r.get(0)));
+        let tables: Vec<String> = {
+            let mut stmt = try!(self.prepare("SELECT name FROM sqlite_master WHERE type = \
+                                              'table' AND name NOT LIKE 'sqlite\\_%' \
+                                              ESCAPE '\\' ORDER BY sql LIKE 'CREATE VIRTUAL%' \
+                                              DESC"));
+            try!(stmt.query_map_into(&[], |row| row.get_checked(0)))
+        };
+
+        if foreign_keys {
+            try!(self.execute_batch("PRAGMA foreign_keys = OFF"));
+        }
+        // The savepoint starts a transaction if none is open, after foreign keys are disabled.
+        let mut result = self.execute_batch("SAVEPOINT drop_all_tables");
+        if result.is_ok() {
+            result = self.drop_tables(&tables);
+            result = match result {
+                Ok(()) => self.execute_batch("RELEASE drop_all_tables"),
+                Err(err) => {
+                    let _ = self.execute_batch("ROLLBACK TO drop_all_tables; \
+                                                RELEASE drop_all_tables");
+                    Err(err)
+                }
+            };
+        }
+        if foreign_keys {
+            let restored = self.execute_batch("PRAGMA foreign_keys = ON");
+            result = result.and(restored);
+        }
+        result
+    }
+
+    fn drop_tables(&self, tables: &[String]) -> Result<()> {
+        let mut exists = try!(self.prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' \
+                                            AND name = ?"));
+        for table in tables {
+            // Dropping a virtual table also drops its shadow tables.
+            if try!(exists.exists(&[table])) {
+                try!(self.execute_batch(&format!("DROP TABLE {}", quote_identifier(table))));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {Connection, Error};
+    use types::Value;
+    use super::{IndexDef, Stat1Row};
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_owned())
+    }
+
+    #[test]
+    fn test_diff_tables() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE a (id INTEGER, name TEXT, qty INTEGER);
+                          CREATE TABLE b (id INTEGER, name TEXT, qty INTEGER);
+                          INSERT INTO a VALUES (1, 'apple', 3), (2, 'pear', 5), (3, 'plum', NULL);
+                          INSERT INTO b VALUES (1, 'apple', 3), (2, 'pear', 6), (3, 'plum', NULL),
+                                               (4, 'fig', 1);
+                          INSERT INTO a VALUES (5, 'kiwi', 2);")
+            .unwrap();
+
+        let diff = db.diff_tables("a", "b", &["id"]).unwrap();
+        assert_eq!(vec!["id", "name", "qty"], diff.columns);
+        assert_eq!(vec![vec![Value::Integer(5), text("kiwi"), Value::Integer(2)]],
+                   diff.only_in_a);
+        assert_eq!(vec![vec![Value::Integer(4), text("fig"), Value::Integer(1)]],
+                   diff.only_in_b);
+        assert_eq!(vec![(vec![Value::Integer(2), text("pear"), Value::Integer(5)],
+                         vec![Value::Integer(2), text("pear"), Value::Integer(6)])],
+                   diff.changed);
+
+        // Without a key, a changed row is reported as removed and added.
+        let diff = db.diff_tables("a", "b", &[]).unwrap();
+        assert_eq!(2, diff.only_in_a.len());
+        assert_eq!(2, diff.only_in_b.len());
+        assert!(diff.changed.is_empty());
+    }
+
+    #[test]
+    fn test_diff_tables_invalid_key() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE a (id INTEGER); CREATE TABLE b (id INTEGER);")
+            .unwrap();
+
+        match db.diff_tables("a", "b", &["nope"]).unwrap_err() {
+            Error::InvalidColumnName(ref name) => assert_eq!("nope", name),
+            err => panic!("Unexpected error {}", err),
+        }
+        assert!(db.diff_tables("a", "missing", &["id"]).is_err());
+    }
+
+    #[test]
+    fn test_drop_all_tables() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("PRAGMA foreign_keys = ON;
+                          CREATE TABLE parent (id INTEGER PRIMARY KEY);
+                          CREATE TABLE child (id INTEGER PRIMARY KEY,
+                                              parent_id INTEGER REFERENCES parent(id));
+                          CREATE TABLE grandchild (child_id INTEGER REFERENCES child(id));
+                          CREATE TABLE auto (id INTEGER PRIMARY KEY AUTOINCREMENT);
+                          INSERT INTO parent VALUES (1);
+                          INSERT INTO child VALUES (1, 1);
+                          INSERT INTO grandchild VALUES (1);
+                          INSERT INTO auto VALUES (NULL);")
+            .unwrap();
+
+        db.drop_all_tables().unwrap();
+
+        let count: i32 = db.query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' \
+                                       AND name NOT LIKE 'sqlite%'",
+                                      &[],
+                                      |r| r.get(0))
+            .unwrap();
+        assert_eq!(0, count);
+        let foreign_keys: bool = db.query_row("PRAGMA foreign_keys", &[], |r| r.get(0)).unwrap();
+        assert!(foreign_keys);
+
+        // dropping an empty schema is fine
+        db.drop_all_tables().unwrap();
+    }
+
+    #[test]
+    fn test_drop_all_tables_with_virtual_tables() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE a (x INTEGER);
+                          CREATE VIRTUAL TABLE t USING fts5(x);
+                          INSERT INTO t VALUES ('hello');")
+            .unwrap();
+
+        db.drop_all_tables().unwrap();
+        let count: i32 = db.query_row("SELECT COUNT(*) FROM sqlite_master", &[], |r| r.get(0))
+            .unwrap();
+        assert_eq!(0, count);
+    }
+
+    #[test]
+    fn test_drop_all_tables_rolls_back() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE a (x INTEGER);
+                          CREATE TABLE b (x INTEGER);
+                          INSERT INTO a VALUES (1);")
+            .unwrap();
+
+        // A running statement on `a` keeps it from being dropped.
+        let mut stmt = db.prepare("SELECT x FROM a").unwrap();
+        let mut rows = stmt.query(&[]).unwrap();
+        assert!(rows.next().is_some());
+        assert!(db.drop_all_tables().is_err());
+        drop(rows);
+
+        let count: i32 = db.query_row("SELECT COUNT(*) FROM sqlite_master", &[], |r| r.get(0))
+            .unwrap();
+        assert_eq!(2, count);
+        assert!(db.is_autocommit());
+    }
+
+    #[test]
+    fn test_indexes() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (a INTEGER, b TEXT, c REAL);
+                          CREATE UNIQUE INDEX foo_b_a ON foo (b, a);
+                          CREATE INDEX foo_c ON foo (c) WHERE c > 0;")
+            .unwrap();
+
+        let mut indexes = db.indexes("foo").unwrap();
+        indexes.sort_by(|x, y| x.name.cmp(&y.name));
+        assert_eq!(vec![IndexDef {
+                            name: "foo_b_a".to_owned(),
+                            unique: true,
+                            partial: false,
+                            columns: vec![Some("b".to_owned()), Some("a".to_owned())],
+                        },
+                        IndexDef {
+                            name: "foo_c".to_owned(),
+                            unique: false,
+                            partial: true,
+                            columns: vec![Some("c".to_owned())],
+                        }],
+                   indexes);
+
+        assert!(db.indexes("missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_analyze_and_stat1() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (a INTEGER, b TEXT);
+                          CREATE INDEX foo_a ON foo (a);
+                          CREATE TABLE bar (x INTEGER);
+                          INSERT INTO foo VALUES (1, 'x');
+                          INSERT INTO foo VALUES (1, 'y');
+                          INSERT INTO foo VALUES (2, 'z');
+                          INSERT INTO bar VALUES (1);")
+            .unwrap();
+        assert!(db.stat1().unwrap().is_empty());
+
+        db.analyze(Some("foo")).unwrap();
+        let stats = db.stat1().unwrap();
+        assert_eq!(vec![Stat1Row {
+                            table: "foo".to_owned(),
+                            index: Some("foo_a".to_owned()),
+                            stat: "3 2".to_owned(),
+                        }],
+                   stats);
+        assert_eq!(Some(3), stats[0].row_count());
+
+        db.analyze(None).unwrap();
+        let stats = db.stat1().unwrap();
+        assert_eq!(2, stats.len());
+        assert_eq!(("bar", None), (&stats[0].table[..], stats[0].index.as_ref()));
+        assert_eq!(Some(1), stats[0].row_count());
+
+        assert!(db.analyze(Some("missing")).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+//! Serialize a database into a byte buffer, and load a database from one.
+use std::cmp;
+use std::os::raw::{c_char, c_int, c_uchar, c_uint, c_void};
+use std::ptr;
+
+use ffi;
+
+use {Connection, DatabaseName, Error, Result};
+
+// These functions require SQLite 3.23.0 or later, compiled with SQLITE_ENABLE_DESERIALIZE (the
+// default since 3.36.0), so they are not part of the pregenerated bindings.
+extern "C" {
+    fn sqlite3_serialize(db: *mut ffi::sqlite3,
+                         schema: *const c_char,
+                         size: *mut ffi::sqlite3_int64,
+                         flags: c_uint)
+                         -> *mut c_uchar;
+    fn sqlite3_deserialize(db: *mut ffi::sqlite3,
+                           schema: *const c_char,
+                           data: *mut c_uchar,
+                           db_size: ffi::sqlite3_int64,
+                           buf_size: ffi::sqlite3_int64,
+                           flags: c_uint)
+                           -> c_int;
+    fn sqlite3_malloc64(size: ffi::sqlite3_uint64) -> *mut c_void;
+}
+
+const SQLITE_DESERIALIZE_FREEONCLOSE: c_uint = 1;
+const SQLITE_DESERIALIZE_RESIZEABLE: c_uint = 2;
+
+impl Connection {
+    /// Serialize the database `schema` into a byte vector that holds the same bytes as the
+    /// database file would on disk. This works for in-memory databases as well.
+    ///
+    /// The bytes are always copied out of SQLite: the memory SQLite could share instead (with
+    /// `SQLITE_SERIALIZE_NOCOPY`) belongs to the database and changes under any later
+    /// statement, so it cannot be handed out safely.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, DatabaseName, Result};
+    /// fn copy_in_memory(src: &Connection) -> Result<Connection> {
+    ///     let bytes = try!(src.serialize(DatabaseName::Main));
+    ///     let dst = try!(Connection::open_in_memory());
+    ///     try!(dst.deserialize(DatabaseName::Main, &bytes));
+    ///     Ok(dst)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if there is no database named `schema`, or if SQLite cannot allocate
+    /// the buffer.
+    pub fn serialize(&self, schema: DatabaseName) -> Result<Vec<u8>> {
+        let schema = try!(schema.to_cstring());
+        let c = self.db.borrow();
+        unsafe {
+            let mut size: ffi::sqlite3_int64 = 0;
+            let data = sqlite3_serialize(c.db(), schema.as_ptr(), &mut size, 0);
+            if data.is_null() {
+                // An empty database has no pages to copy.
+                if size == 0 {
+                    return Ok(Vec::new());
+                }
+                return Err(Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_NOMEM), None));
+            }
+            let bytes = ::std::slice::from_raw_parts(data, size as usize).to_vec();
+            ffi::sqlite3_free(data as *mut c_void);
+            Ok(bytes)
+        }
+    }
+
+    /// Replace the database `schema` with the database serialized in `bytes` (e.g., by
+    /// `serialize`). The database is held in memory, starting from a copy of `bytes`, and can
+    /// be modified and grow; changes are not written anywhere else.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if there is no database named `schema`, if a statement is running or
+    /// a transaction is open on this connection, if SQLite cannot allocate the buffer, or if
+    /// `bytes` is not a valid database (which may only be detected by the first query).
+    pub fn deserialize(&self, schema: DatabaseName, bytes: &[u8]) -> Result<()> {
+        let schema = try!(schema.to_cstring());
+        let mut c = self.db.borrow_mut();
+        unsafe {
+            // SQLite takes ownership of the buffer, so it must come from sqlite3_malloc. Allocate
+            // at least one byte, since sqlite3_malloc64(0) returns NULL and the copy below needs
+            // a valid pointer even when `bytes` is empty.
+            let n = cmp::max(bytes.len(), 1);
+            let data = sqlite3_malloc64(n as ffi::sqlite3_uint64) as *mut c_uchar;
+            if data.is_null() {
+                return Err(Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_NOMEM), None));
+            }
+            ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+            // On failure, SQLite frees the buffer because of SQLITE_DESERIALIZE_FREEONCLOSE.
+            let r = sqlite3_deserialize(c.db(),
+                                        schema.as_ptr(),
+                                        data,
+                                        bytes.len() as ffi::sqlite3_int64,
+                                        n as ffi::sqlite3_int64,
+                                        SQLITE_DESERIALIZE_FREEONCLOSE |
+                                        SQLITE_DESERIALIZE_RESIZEABLE);
+            c.decode_result(r)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {Connection, DatabaseName};
+
+    #[test]
+    fn test_serialize_deserialize() {
+        let src = Connection::open_in_memory().unwrap();
+        src.execute_batch("CREATE TABLE foo (x INTEGER, t TEXT);
+                           INSERT INTO foo VALUES (1, 'one');
+                           INSERT INTO foo VALUES (2, 'two');")
+            .unwrap();
+        let bytes = src.serialize(DatabaseName::Main).unwrap();
+        assert_eq!(b"SQLite format 3\0", &bytes[..16]);
+
+        let dst = Connection::open_in_memory().unwrap();
+        dst.deserialize(DatabaseName::Main, &bytes).unwrap();
+        let t: String = dst.query_row("SELECT t FROM foo WHERE x = 2", &[], |r| r.get(0))
+            .unwrap();
+        assert_eq!("two", t);
+
+        // The deserialized database is writable and independent of `bytes`.
+        dst.execute("INSERT INTO foo VALUES (3, 'three')", &[]).unwrap();
+        let count: i64 = dst.query_row("SELECT COUNT(*) FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!(3, count);
+        assert_eq!(bytes, src.serialize(DatabaseName::Main).unwrap());
+    }
+
+    #[test]
+    fn test_deserialize_empty() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (x INTEGER)").unwrap();
+        db.deserialize(DatabaseName::Main, &[]).unwrap();
+
+        let count: i64 = db.query_row("SELECT COUNT(*) FROM sqlite_master", &[], |r| r.get(0))
+            .unwrap();
+        assert_eq!(0, count);
+        db.execute_batch("CREATE TABLE foo (x INTEGER); INSERT INTO foo VALUES (1);").unwrap();
+    }
+
+    #[test]
+    fn test_serialize_failures() {
+        let db = Connection::open_in_memory().unwrap();
+        assert!(db.serialize(DatabaseName::Attached("missing")).is_err());
+        assert!(db.deserialize(DatabaseName::Attached("missing"), &[]).is_err());
+    }
+}
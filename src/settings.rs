@@ -0,0 +1,136 @@
+//! Capture and restore connection-level settings.
+use std::time::Duration;
+
+use ffi;
+
+use {Connection, Error, Result};
+
+/// A snapshot of the connection-level settings that are commonly changed with `PRAGMA`s,
+/// created by `Connection::snapshot_settings` and applied by `Connection::apply_settings`.
+///
+/// The fields are public, so a `Settings` can also be built or tweaked by hand (e.g., to
+/// describe the state a connection pool hands out).
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Settings {
+    /// `PRAGMA foreign_keys`.
+    pub foreign_keys: bool,
+    /// `PRAGMA recursive_triggers`.
+    pub recursive_triggers: bool,
+    /// The busy timeout, in milliseconds (`PRAGMA busy_timeout`).
+    pub busy_timeout: i32,
+    /// `PRAGMA journal_mode` of the main database, in lower case (e.g., `"wal"`).
+    pub journal_mode: String,
+    /// `PRAGMA synchronous` of the main database (0 = `OFF`, 1 = `NORMAL`, 2 = `FULL`,
+    /// 3 = `EXTRA`).
+    pub synchronous: i32,
+    /// `PRAGMA cache_size` of the main database: a number of pages if positive, or a number of
+    /// KiB if negative.
+    pub cache_size: i64,
+}
+
+impl Connection {
+    /// Capture the current values of the settings in `Settings`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if one of the `PRAGMA`s cannot be read. `PRAGMA busy_timeout`
+    /// requires SQLite 3.7.15 or later.
+    pub fn snapshot_settings(&self) -> Result<Settings> {
+        Ok(Settings {
+               foreign_keys: try!(self.query_row("PRAGMA foreign_keys", &[], |r| r.get(0))),
+               recursive_triggers: try!(self.query_row("PRAGMA recursive_triggers",
+                                                       &[],
+                                                       |r| r.get(0))),
+               busy_timeout: try!(self.query_row("PRAGMA busy_timeout", &[], |r| r.get(0))),
+               journal_mode: try!(self.query_row("PRAGMA journal_mode", &[], |r| r.get(0))),
+               synchronous: try!(self.query_row("PRAGMA synchronous", &[], |r| r.get(0))),
+               cache_size: try!(self.query_row("PRAGMA cache_size", &[], |r| r.get(0))),
+           })
+    }
+
+    /// Apply all the settings in `settings`, e.g., to restore a snapshot taken with
+    /// `snapshot_settings`. Setting the busy timeout replaces any busy handler.
+    ///
+    /// Some settings cannot be changed in every state: `foreign_keys` and `journal_mode`
+    /// changes are ignored by SQLite inside a transaction, and in-memory databases only support
+    /// the `memory` and `off` journal modes.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if one of the `PRAGMA`s fails, or if the journal mode could not be
+    /// changed.
+    pub fn apply_settings(&self, settings: &Settings) -> Result<()> {
+        try!(self.execute_batch(&format!("PRAGMA foreign_keys = {};
+                                          PRAGMA recursive_triggers = {};
+                                          PRAGMA synchronous = {};
+                                          PRAGMA cache_size = {};",
+                                         settings.foreign_keys as i32,
+                                         settings.recursive_triggers as i32,
+                                         settings.synchronous,
+                                         settings.cache_size)));
+        try!(self.busy_timeout(Duration::from_millis(settings.busy_timeout.max(0) as u64)));
+
+        // Unknown modes are silently ignored, so use the returned mode to check it was applied.
+        let journal_mode: String =
+            try!(self.query_row(&format!("PRAGMA journal_mode = {}",
+                                         ::quote_identifier(&settings.journal_mode)),
+                                &[],
+                                |r| r.get(0)));
+        if !journal_mode.eq_ignore_ascii_case(&settings.journal_mode) {
+            let msg = format!("could not change the journal mode from {} to {}",
+                              journal_mode,
+                              settings.journal_mode);
+            return Err(Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_MISUSE), Some(msg)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate tempdir;
+
+    use self::tempdir::TempDir;
+    use Connection;
+
+    #[test]
+    fn test_snapshot_and_apply_settings() {
+        let temp_dir = TempDir::new("test_snapshot_and_apply_settings").unwrap();
+        let db = Connection::open(temp_dir.path().join("test.db3")).unwrap();
+
+        db.execute_batch("PRAGMA foreign_keys = ON;
+                          PRAGMA synchronous = OFF;
+                          PRAGMA cache_size = -1024;
+                          PRAGMA busy_timeout = 1234;
+                          PRAGMA journal_mode = WAL;")
+            .unwrap();
+        let snapshot = db.snapshot_settings().unwrap();
+        assert!(snapshot.foreign_keys);
+        assert!(!snapshot.recursive_triggers);
+        assert_eq!(1234, snapshot.busy_timeout);
+        assert_eq!("wal", snapshot.journal_mode);
+        assert_eq!(0, snapshot.synchronous);
+        assert_eq!(-1024, snapshot.cache_size);
+
+        db.execute_batch("PRAGMA foreign_keys = OFF;
+                          PRAGMA synchronous = FULL;
+                          PRAGMA cache_size = 100;
+                          PRAGMA busy_timeout = 0;
+                          PRAGMA journal_mode = DELETE;")
+            .unwrap();
+        assert!(db.snapshot_settings().unwrap() != snapshot);
+
+        db.apply_settings(&snapshot).unwrap();
+        assert_eq!(snapshot, db.snapshot_settings().unwrap());
+    }
+
+    #[test]
+    fn test_apply_unsupported_journal_mode() {
+        let db = Connection::open_in_memory().unwrap();
+        let mut settings = db.snapshot_settings().unwrap();
+        assert_eq!("memory", settings.journal_mode);
+
+        settings.journal_mode = "wal".to_owned();
+        assert!(db.apply_settings(&settings).is_err());
+    }
+}
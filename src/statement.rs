@@ -1,4 +1,5 @@
 use std::{convert, fmt, mem, ptr, result, str};
+use std::collections::HashMap;
 use std::ffi::CStr;
 use std::os::raw::{c_char, c_int, c_void};
 use std::slice::from_raw_parts;
@@ -6,10 +7,34 @@ use std::slice::from_raw_parts;
 use super::ffi;
 use super::{Connection, RawStatement, Result, Error, ValueRef, Row, Rows, AndThenRows, MappedRows};
 use super::str_to_cstring;
-use types::{ToSql, ToSqlOutput};
+use types::{FromSql, Null, ToSql, ToSqlOutput, Value};
 use row::{RowsCrateImpl, MappedRowsCrateImpl, AndThenRowsCrateImpl};
 
+// Requires SQLite 3.27.0 or later, compiled with SQLITE_ENABLE_NORMALIZE, so it is not part of
+// the pregenerated bindings.
+#[cfg(feature = "normalize")]
+extern "C" {
+    fn sqlite3_normalized_sql(stmt: *mut ffi::sqlite3_stmt) -> *const c_char;
+}
+
+/// Options for how `Statement::execute_named_map` handles entries whose names do not match any
+/// parameter of the statement.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum UnknownParameters {
+    /// Silently skip entries that do not name a parameter.
+    Ignore,
+
+    /// Fail with `Error::InvalidParameterName` if any entry does not name a parameter.
+    Error,
+}
+
 /// A prepared statement.
+///
+/// A statement can be executed any number of times (e.g., in successive transactions). If a
+/// previous execution was not run to completion, the statement is automatically reset before
+/// its parameters are bound again. If the database schema changes after the statement is
+/// prepared (e.g., a column or an index is added by this or another connection), SQLite
+/// transparently re-prepares it the next time it is run instead of failing with `SQLITE_SCHEMA`.
 pub struct Statement<'conn> {
     conn: &'conn Connection,
     stmt: RawStatement,
@@ -28,6 +53,67 @@ impl<'conn> Statement<'conn> {
         cols
     }
 
+    /// Get the declared type of column `col` of the result set (e.g., `"INTEGER"` or
+    /// `"VARCHAR(20)"`), as written in the `CREATE TABLE` statement.
+    ///
+    /// Returns `None` if the column is an expression or subquery rather than a table column, if
+    /// the column was declared without a type, or if `col` is out of range. Note that SQLite's
+    /// dynamic typing means values in the column are not guaranteed to be of the declared type.
+    pub fn column_decltype(&self, col: i32) -> Option<&str> {
+        unsafe { self.column_metadata(ffi::sqlite3_column_decltype(self.stmt.ptr(), col)) }
+    }
+
+    /// Get the name of the table that column `col` of the result set comes from, or `None` if
+    /// the column is an expression or subquery rather than a table column or if `col` is out of
+    /// range. The name is that of the table itself, not of any alias used in the query.
+    ///
+    /// This requires SQLite to be compiled with `SQLITE_ENABLE_COLUMN_METADATA` (as the bundled
+    /// SQLite is); enable the `column_metadata` feature only if it is.
+    #[cfg(feature = "column_metadata")]
+    pub fn column_table_name(&self, col: i32) -> Option<&str> {
+        unsafe { self.column_metadata(ffi::sqlite3_column_table_name(self.stmt.ptr(), col)) }
+    }
+
+    /// Get the name of the table column that column `col` of the result set comes from, or `None`
+    /// if the column is an expression or subquery rather than a table column or if `col` is out
+    /// of range. The name is that of the table column itself, not of any alias (`AS` clause)
+    /// used in the query.
+    ///
+    /// This requires SQLite to be compiled with `SQLITE_ENABLE_COLUMN_METADATA` (as the bundled
+    /// SQLite is); enable the `column_metadata` feature only if it is.
+    #[cfg(feature = "column_metadata")]
+    pub fn column_origin_name(&self, col: i32) -> Option<&str> {
+        unsafe { self.column_metadata(ffi::sqlite3_column_origin_name(self.stmt.ptr(), col)) }
+    }
+
+    // SQLite keeps column metadata alive until the statement is finalized.
+    unsafe fn column_metadata(&self, ptr: *const c_char) -> Option<&str> {
+        if ptr.is_null() {
+            None
+        } else {
+            Some(str::from_utf8(CStr::from_ptr(ptr).to_bytes()).unwrap())
+        }
+    }
+
+    /// Get the normalized SQL text of the statement: its SQL with literals replaced by `?` and
+    /// whitespace and keyword case normalized, so that statements that only differ in their
+    /// literals (e.g., `WHERE id = 1` and `WHERE id = 2`) have the same normalized SQL.
+    ///
+    /// Returns `None` if SQLite cannot produce the normalized text (e.g., when out of memory).
+    /// The `normalize` feature requires an SQLite built with `SQLITE_ENABLE_NORMALIZE`; with
+    /// any other build, linking fails.
+    #[cfg(feature = "normalize")]
+    pub fn normalized_sql(&self) -> Option<String> {
+        unsafe {
+            let sql = sqlite3_normalized_sql(self.stmt.ptr());
+            if sql.is_null() {
+                None
+            } else {
+                Some(String::from_utf8_lossy(CStr::from_ptr(sql).to_bytes()).into_owned())
+            }
+        }
+    }
+
     /// Return the number of columns in the result set returned by the prepared statement.
     pub fn column_count(&self) -> i32 {
         self.stmt.column_count()
@@ -38,6 +124,10 @@ impl<'conn> Statement<'conn> {
     /// If there is no AS clause then the name of the column is unspecified and may change from one
     /// release of SQLite to the next.
     ///
+    /// If several columns have the same name (e.g., `id` in a join), the index of the first one
+    /// is returned; this is also the column used by `Row::get` with a name. Use
+    /// `column_index_all` to find the others.
+    ///
     /// # Failure
     ///
     /// Will return an `Error::InvalidColumnName` when there is no column with the specified `name`.
@@ -52,6 +142,76 @@ impl<'conn> Statement<'conn> {
         Err(Error::InvalidColumnName(String::from(name)))
     }
 
+    /// Returns the indexes, in order, of all the columns in the result set with the given name.
+    /// The returned vector is empty if there is no such column.
+    pub fn column_index_all(&self, name: &str) -> Vec<i32> {
+        let bytes = name.as_bytes();
+        (0..self.column_count())
+            .filter(|&i| bytes == self.stmt.column_name(i).to_bytes())
+            .collect()
+    }
+
+    /// Returns a rough estimate of the number of rows the statement visits, without running it.
+    ///
+    /// The estimate is computed from the statement's `EXPLAIN QUERY PLAN`: each full scan of a
+    /// table counts as the table's size, each index search counts as the average number of rows
+    /// per key recorded by `ANALYZE` (or a fixed guess if the index has not been analyzed),
+    /// and nested loops multiply. The table of each loop is identified from the tables that the
+    /// statement's bytecode (`EXPLAIN`) opens, so aliases and quoted names are handled. Table
+    /// sizes come from `sqlite_stat1` when available. Otherwise the largest rowid is used, which
+    /// overestimates the size of tables whose rows have been deleted or given explicit rowids,
+    /// and `WITHOUT ROWID` tables get the same fixed guess as unanalyzed indexes. `LIMIT`,
+    /// aggregates, subqueries, virtual tables and `WHERE` terms that are not used by an index
+    /// are not taken into account.
+    ///
+    /// This is suitable for progress reporting, but it is only an estimate: it may be off by
+    /// orders of magnitude, and it is not the number of rows the statement returns. Use
+    /// `Connection::count_query` to count rows exactly.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the query plan cannot be computed.
+    pub fn estimated_row_count(&self) -> Result<i64> {
+        let sql = try!(str::from_utf8(self.stmt.sql().to_bytes()));
+        let details = try!(explain(self.conn,
+                                   "EXPLAIN QUERY PLAN",
+                                   sql,
+                                   |row| row.get_checked::<_, String>(3)));
+        let entries: Vec<&str> = details.iter()
+            .map(|detail| detail.as_str())
+            .filter(|detail| detail.starts_with("SCAN ") || detail.starts_with("SEARCH "))
+            .collect();
+        let loops = try!(table_loops(self.conn, sql));
+
+        // Match the plan entries with the tables they loop over: by index or table name first,
+        // then, if the remaining entries name their table by an alias, in order.
+        let mut matched = vec![None; entries.len()];
+        let mut claimed = vec![false; loops.len()];
+        for (i, entry) in entries.iter().enumerate() {
+            matched[i] = find_loop(&loops, &claimed, entry);
+            if let Some(j) = matched[i] {
+                claimed[j] = true;
+            }
+        }
+        let unmatched: Vec<usize> = (0..entries.len())
+            .filter(|&i| matched[i].is_none() && entries[i] != "SCAN CONSTANT ROW")
+            .collect();
+        let unclaimed: Vec<usize> = (0..loops.len()).filter(|&j| !claimed[j]).collect();
+        if unmatched.len() == unclaimed.len() {
+            for (&i, &j) in unmatched.iter().zip(&unclaimed) {
+                matched[i] = Some(j);
+            }
+        }
+
+        let mut estimate = 1i64;
+        for (entry, j) in entries.iter().zip(matched) {
+            if let Some(j) = j {
+                estimate = estimate.saturating_mul(loop_rows(self.conn, entry, &loops[j]));
+            }
+        }
+        Ok(estimate)
+    }
+
     /// Execute the prepared statement.
     ///
     /// On success, returns the number of rows that were changed or inserted or deleted (via
@@ -74,12 +234,38 @@ impl<'conn> Statement<'conn> {
     /// # Failure
     ///
     /// Will return `Err` if binding parameters fails, the executed statement returns rows (in
-    /// which case `query` should be used instead), or the underling SQLite call fails.
+    /// which case `query` or `execute_returning` should be used instead), or the underling
+    /// SQLite call fails. A statement that returns rows and modifies the database (e.g., an
+    /// `INSERT ... RETURNING`) is rejected without being run.
     pub fn execute(&mut self, params: &[&ToSql]) -> Result<c_int> {
         try!(self.bind_parameters(params));
         self.execute_with_bound_parameters()
     }
 
+    /// Like `execute`, but takes owned, boxed parameters (e.g., a `Vec<Box<ToSql>>` built at
+    /// runtime), which are bound positionally.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use rusqlite::types::ToSql;
+    /// fn insert(conn: &Connection, name: Option<String>, age: i32) -> Result<i32> {
+    ///     let mut params: Vec<Box<ToSql>> = vec![Box::new(age)];
+    ///     params.push(Box::new(name));
+    ///     let mut stmt = try!(conn.prepare("INSERT INTO people (age, name) VALUES (?, ?)"));
+    ///     stmt.execute_boxed(&params)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` for the same reasons as `execute`.
+    pub fn execute_boxed(&mut self, params: &[Box<ToSql>]) -> Result<c_int> {
+        let params: Vec<&ToSql> = params.iter().map(|p| &**p).collect();
+        self.execute(&params)
+    }
+
     /// Execute the prepared statement with named parameter(s). If any parameters
     /// that were in the prepared statement are not included in `params`, they
     /// will continue to use the most-recently bound value from a previous call
@@ -107,6 +293,47 @@ impl<'conn> Statement<'conn> {
         self.execute_with_bound_parameters()
     }
 
+    /// Execute the prepared statement, binding each entry of `params` to the parameter of the
+    /// same name (including its prefix, e.g., `":name"`).
+    ///
+    /// This is useful when both the SQL and its parameters are only known at runtime. Entries
+    /// that do not name a parameter of the statement are handled according to `unknown`.
+    ///
+    /// On success, returns the number of rows that were changed or inserted or deleted (via
+    /// `sqlite3_changes`).
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, UnknownParameters};
+    /// # use rusqlite::types::Value;
+    /// # use std::collections::HashMap;
+    /// fn insert(conn: &Connection, params: &HashMap<String, Value>) -> Result<i32> {
+    ///     let mut stmt = try!(conn.prepare("INSERT INTO test (name) VALUES (:name)"));
+    ///     stmt.execute_named_map(params, UnknownParameters::Ignore)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails (including an unknown name when `unknown`
+    /// is `UnknownParameters::Error`), the executed statement returns rows (in which case
+    /// `query` should be used instead), or the underling SQLite call fails.
+    pub fn execute_named_map(&mut self,
+                             params: &HashMap<String, Value>,
+                             unknown: UnknownParameters)
+                             -> Result<c_int> {
+        self.reset_if_busy();
+        for (name, value) in params {
+            match try!(self.parameter_index(name)) {
+                Some(i) => try!(self.bind_parameter(value, i)),
+                None if unknown == UnknownParameters::Ignore => (),
+                None => return Err(Error::InvalidParameterName(name.clone())),
+            }
+        }
+        self.execute_with_bound_parameters()
+    }
+
     /// Execute an INSERT and return the ROWID.
     ///
     /// # Note
@@ -159,6 +386,16 @@ impl<'conn> Statement<'conn> {
         Ok(Rows::new(self))
     }
 
+    /// Like `query`, but takes owned, boxed parameters, which are bound positionally.
+    ///
+    /// ## Failure
+    ///
+    /// Will return `Err` if binding parameters fails.
+    pub fn query_boxed<'a>(&'a mut self, params: &[Box<ToSql>]) -> Result<Rows<'a>> {
+        let params: Vec<&ToSql> = params.iter().map(|p| &**p).collect();
+        self.query(&params)
+    }
+
     /// Execute the prepared statement with named parameter(s), returning a handle for the
     /// resulting rows. If any parameters that were in the prepared statement are not included in
     /// `params`, they will continue to use the most-recently bound value from a previous call to
@@ -252,6 +489,122 @@ impl<'conn> Statement<'conn> {
         Ok(MappedRows::new(rows, f))
     }
 
+    /// Execute the prepared statement and collect the result of calling `f` on each of the
+    /// query's rows into a `Vec`.
+    ///
+    /// This is equivalent to collecting `query_and_then`, but `f` is called directly in a loop
+    /// over the rows, which avoids the iterator adapters on hot paths. Collection stops at the
+    /// first error returned by SQLite or by `f`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn get_names(conn: &Connection) -> Result<Vec<String>> {
+    ///     let mut stmt = try!(conn.prepare("SELECT name FROM people"));
+    ///     stmt.query_map_into(&[], |row| row.get_checked(0))
+    /// }
+    /// ```
+    ///
+    /// ## Failure
+    ///
+    /// Will return `Err` if binding parameters fails, if stepping the statement fails or if
+    /// `f` returns an error.
+    pub fn query_map_into<T, F>(&mut self, params: &[&ToSql], mut f: F) -> Result<Vec<T>>
+        where F: FnMut(&Row) -> Result<T>
+    {
+        let mut rows = try!(self.query(params));
+        let mut values = Vec::new();
+        while let Some(row) = rows.next() {
+            let row = try!(row);
+            values.push(try!(f(&row)));
+        }
+        Ok(values)
+    }
+
+    /// Execute the prepared statement, which may return rows (e.g., an INSERT, UPDATE or DELETE
+    /// with a `RETURNING` clause), mapping `f` over all of the returned rows.
+    ///
+    /// Unlike `execute`, which fails with `ExecuteReturnedResults` if the statement returns
+    /// rows, this always runs the statement to completion; a statement that returns no rows
+    /// yields an empty `Vec`. Use `Connection::changes` to get the number of modified rows.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn delete_expired(conn: &Connection) -> Result<Vec<String>> {
+    ///     let mut stmt = try!(conn.prepare("DELETE FROM sessions WHERE expires < ? \
+    ///                                       RETURNING token"));
+    ///     stmt.execute_returning(&[&1_500_000_000i64], |row| row.get_checked(0))
+    /// }
+    /// ```
+    ///
+    /// ## Failure
+    ///
+    /// Will return `Err` if binding parameters fails, if stepping the statement fails or if
+    /// `f` returns an error.
+    pub fn execute_returning<T, F>(&mut self, params: &[&ToSql], f: F) -> Result<Vec<T>>
+        where F: FnMut(&Row) -> Result<T>
+    {
+        self.query_map_into(params, f)
+    }
+
+    /// Execute the prepared statement, returning the column names and all of the resulting rows
+    /// as owned `Value`s (row-major).
+    ///
+    /// This is useful for generic consumers (e.g., a REPL) that do not know the schema of the
+    /// result. NULLs are returned as `Value::Null`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn print_all(conn: &Connection, sql: &str) -> Result<()> {
+    ///     let mut stmt = try!(conn.prepare(sql));
+    ///     let (names, rows) = try!(stmt.query_matrix(&[]));
+    ///     println!("{:?}", names);
+    ///     for row in rows {
+    ///         println!("{:?}", row);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// ## Failure
+    ///
+    /// Will return `Err` if binding parameters fails or if stepping the statement fails.
+    pub fn query_matrix(&mut self, params: &[&ToSql]) -> Result<(Vec<String>, Vec<Vec<Value>>)> {
+        let names = self.column_names().into_iter().map(String::from).collect();
+        let n = self.column_count();
+        let rows = try!(self.query_map_into(params,
+                                            |row| (0..n).map(|i| row.get_checked(i)).collect()));
+        Ok((names, rows))
+    }
+
+    /// Execute the prepared statement, returning the values of column `col` in all of the
+    /// resulting rows.
+    ///
+    /// Use `Option<T>` as the value type if the column can contain NULLs.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn get_ages(conn: &Connection) -> Result<Vec<Option<i64>>> {
+    ///     let mut stmt = try!(conn.prepare("SELECT name, age FROM people"));
+    ///     stmt.column_values(&[], 1)
+    /// }
+    /// ```
+    ///
+    /// ## Failure
+    ///
+    /// Will return `Err` if binding parameters fails, if stepping the statement fails, if `col`
+    /// is out of range, or if a value cannot be converted to `T`.
+    pub fn column_values<T: FromSql>(&mut self, params: &[&ToSql], col: i32) -> Result<Vec<T>> {
+        self.query_map_into(params, |row| row.get_checked(col))
+    }
+
     /// Executes the prepared statement and maps a function over the resulting
     /// rows, where the function returns a `Result` with `Error` type implementing
     /// `std::convert::From<Error>` (so errors can be unified).
@@ -367,7 +720,16 @@ impl<'conn> Statement<'conn> {
         Ok(self.stmt.bind_parameter_index(&c_name))
     }
 
+    // Reset the statement if it was left in the middle of a previous execution, so that it
+    // can be bound and stepped again.
+    fn reset_if_busy(&self) {
+        if self.stmt.is_busy() {
+            self.stmt.reset();
+        }
+    }
+
     fn bind_parameters(&mut self, params: &[&ToSql]) -> Result<()> {
+        self.reset_if_busy();
         assert_eq!(params.len() as c_int, self.stmt.bind_parameter_count(),
                 "incorrect number of parameters to query(): expected {}, got {}",
                 self.stmt.bind_parameter_count(),
@@ -381,6 +743,7 @@ impl<'conn> Statement<'conn> {
     }
 
     fn bind_parameters_named(&mut self, params: &[(&str, &ToSql)]) -> Result<()> {
+        self.reset_if_busy();
         for &(name, value) in params {
             if let Some(i) = try!(self.parameter_index(name)) {
                 try!(self.bind_parameter(value, i));
@@ -446,12 +809,17 @@ impl<'conn> Statement<'conn> {
     }
 
     fn execute_with_bound_parameters(&mut self) -> Result<c_int> {
+        // Stepping a statement such as `INSERT ... RETURNING` once performs all of its changes,
+        // so reject statements that return rows and write to the database before running them.
+        if self.column_count() > 0 && !self.stmt.readonly() {
+            return Err(Error::ExecuteReturnedResults);
+        }
         let r = self.stmt.step();
         self.stmt.reset();
         match r {
             ffi::SQLITE_DONE => {
                 if self.column_count() == 0 {
-                    Ok(self.conn.changes())
+                    Ok(self.conn.changes() as c_int)
                 } else {
                     Err(Error::ExecuteReturnedResults)
                 }
@@ -468,6 +836,171 @@ impl<'conn> Statement<'conn> {
     }
 }
 
+// Prepares `prefix sql` (`EXPLAIN` or `EXPLAIN QUERY PLAN`) and collects its rows; neither
+// depends on the values of the statement's parameters.
+fn explain<T, F>(conn: &Connection, prefix: &str, sql: &str, f: F) -> Result<Vec<T>>
+    where F: FnMut(&Row) -> Result<T>
+{
+    let mut stmt = try!(conn.prepare(&format!("{} {}", prefix, sql)));
+    let params: Vec<&ToSql> = vec![&Null; stmt.stmt.bind_parameter_count() as usize];
+    stmt.query_map_into(&params, f)
+}
+
+// A table that a statement loops over, and the index it uses to do so, if any.
+struct TableLoop {
+    schema: String,
+    table: String,
+    index: Option<String>,
+}
+
+// The tables read by `sql`, found from the root pages its bytecode opens, in the order of the
+// loops of its query plan. A table searched through an index opens the table and then the
+// index; a covering index is opened without its table.
+fn table_loops(conn: &Connection, sql: &str) -> Result<Vec<TableLoop>> {
+    let mut databases = try!(conn.prepare("PRAGMA database_list"));
+    let schemas = try!(databases.query_map_into(&[], |row| {
+        Ok((try!(row.get_checked::<_, i64>(0)), try!(row.get_checked::<_, String>(1))))
+    }));
+    let opened = try!(explain(conn, "EXPLAIN", sql, |row| {
+        let opcode = try!(row.get_checked::<_, String>(1));
+        if opcode == "OpenRead" || opcode == "ReopenIdx" {
+            Ok(Some((try!(row.get_checked::<_, i64>(3)), try!(row.get_checked::<_, i64>(4)))))
+        } else {
+            Ok(None)
+        }
+    }));
+
+    let mut loops: Vec<TableLoop> = Vec::new();
+    for (root, db) in opened.into_iter().filter_map(|op| op) {
+        let schema = match schemas.iter().find(|&&(seq, _)| seq == db) {
+            Some(&(_, ref name)) => name.clone(),
+            None => continue,
+        };
+        // A WITHOUT ROWID table has the same root page as its primary key index.
+        let sql = format!("SELECT type = 'table', name, tbl_name FROM {}.sqlite_master \
+                           WHERE rootpage = ? ORDER BY type = 'table' DESC LIMIT 1",
+                          ::quote_identifier(&schema));
+        let (is_table, name, table) = match conn.query_row(&sql, &[&root], |row| {
+            (row.get::<_, bool>(0), row.get::<_, String>(1), row.get::<_, String>(2))
+        }) {
+            Ok(found) => found,
+            Err(Error::QueryReturnedNoRows) => continue,
+            Err(err) => return Err(err),
+        };
+        if !is_table {
+            if let Some(last) = loops.last_mut() {
+                if last.index.is_none() && last.schema == schema && last.table == table {
+                    last.index = Some(name);
+                    continue;
+                }
+            }
+        }
+        loops.push(TableLoop {
+                       schema: schema,
+                       table: table,
+                       index: if is_table { None } else { Some(name) },
+                   });
+    }
+    Ok(loops)
+}
+
+// The first unclaimed loop whose index is used by the plan `entry`, or else the one whose table
+// is named by it (the longest name wins, since names may contain spaces).
+fn find_loop(loops: &[TableLoop], claimed: &[bool], entry: &str) -> Option<usize> {
+    let uses_index = |index: &str| {
+        let using = format!(" INDEX {}", index);
+        entry.ends_with(&using) || entry.contains(&format!("{} (", using))
+    };
+    let rest = entry.splitn(2, ' ').nth(1).unwrap_or("");
+    // Older versions of SQLite write `SCAN TABLE name`.
+    let names_table = |table: &str| {
+        [rest, if rest.starts_with("TABLE ") { &rest[6..] } else { rest }]
+            .iter()
+            .any(|rest| *rest == table || rest.starts_with(&format!("{} ", table)))
+    };
+
+    let unclaimed = (0..loops.len()).filter(|&j| !claimed[j]);
+    let by_index = unclaimed.clone()
+        .find(|&j| loops[j].index.as_ref().map_or(false, |index| uses_index(index)));
+    by_index.or_else(|| {
+        unclaimed.filter(|&j| names_table(&loops[j].table))
+            .fold(None, |best: Option<usize>, j| match best {
+                Some(b) if loops[b].table.len() >= loops[j].table.len() => best,
+                _ => Some(j),
+            })
+    })
+}
+
+// Estimates the rows visited by the plan `entry` (e.g., `SCAN foo` or
+// `SEARCH foo USING INDEX ix (x=?)`), which loops over `table`.
+fn loop_rows(conn: &Connection, entry: &str, table: &TableLoop) -> i64 {
+    let rows = table_rows(conn, table);
+    if entry.starts_with("SCAN ") {
+        return rows;
+    }
+
+    let using = entry.rfind(" USING ").map_or("", |i| &entry[i..]);
+    if using.contains('<') || using.contains('>') {
+        return rows / 4;
+    }
+    if using.contains(" PRIMARY KEY ") {
+        return 1;
+    }
+    let per_key = table.index.as_ref().and_then(|index| stat1(conn, table, Some(index), 1));
+    per_key.unwrap_or_else(|| ::std::cmp::min(rows, UNKNOWN_ROWS))
+}
+
+// The number of rows assumed when there are no statistics to estimate it from.
+const UNKNOWN_ROWS: i64 = 10;
+
+// Estimated number of rows in `table`. Without `sqlite_stat1`, this is the largest rowid, which
+// is cheap to find but may be far above the number of rows (rows may have been deleted, and
+// rowids can be set explicitly).
+fn table_rows(conn: &Connection, table: &TableLoop) -> i64 {
+    stat1(conn, table, None, 0).or_else(|| max_rowid(conn, table)).unwrap_or(UNKNOWN_ROWS)
+}
+
+// The largest rowid of `table`, or `None` for a WITHOUT ROWID table or one whose columns hide
+// every name of the rowid.
+fn max_rowid(conn: &Connection, table: &TableLoop) -> Option<i64> {
+    let name = format!("{}.{}",
+                       ::quote_identifier(&table.schema),
+                       ::quote_identifier(&table.table));
+    let columns = match conn.prepare(&format!("SELECT * FROM {}", name)) {
+        Ok(stmt) => stmt.column_names().iter().map(|c| c.to_lowercase()).collect::<Vec<_>>(),
+        Err(_) => return None,
+    };
+    ["rowid", "_rowid_", "oid"]
+        .iter()
+        .find(|rowid| !columns.iter().any(|c| c == *rowid))
+        .and_then(|rowid| {
+            conn.query_row(&format!("SELECT max({}) FROM {}", rowid, name),
+                           &[],
+                           |row| row.get::<_, Option<i64>>(0).unwrap_or(0))
+                .ok()
+        })
+}
+
+// The `n`th number of the `sqlite_stat1` entry for `table` and `index`, or for any index of
+// `table` if `index` is `None` (the first number is the number of rows of the table in all of
+// them).
+fn stat1(conn: &Connection, table: &TableLoop, index: Option<&String>, n: usize) -> Option<i64> {
+    let stat1 = format!("{}.sqlite_stat1", ::quote_identifier(&table.schema));
+    let stat = match index {
+        Some(index) => {
+            conn.query_row(&format!("SELECT stat FROM {} WHERE tbl = ? AND idx = ?", stat1),
+                           &[&table.table, index],
+                           |row| row.get::<_, String>(0))
+        }
+        None => {
+            conn.query_row(&format!("SELECT stat FROM {} WHERE tbl = ? LIMIT 1", stat1),
+                           &[&table.table],
+                           |row| row.get::<_, String>(0))
+        }
+    };
+    stat.ok().and_then(|stat| stat.split_whitespace().nth(n).and_then(|s| s.parse().ok()))
+}
+
 impl<'conn> Into<RawStatement> for Statement<'conn> {
     fn into(mut self) -> RawStatement {
         let mut stmt = RawStatement::new(ptr::null_mut());
@@ -590,6 +1123,37 @@ mod test {
                        .unwrap());
     }
 
+    #[test]
+    fn test_execute_named_map() {
+        use std::collections::HashMap;
+        use types::Value;
+        use super::UnknownParameters;
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER, y TEXT)").unwrap();
+
+        let mut params = HashMap::new();
+        params.insert(":x".to_owned(), Value::Integer(1));
+        params.insert(":y".to_owned(), Value::Text("one".to_owned()));
+        params.insert(":unused".to_owned(), Value::Null);
+
+        let mut stmt = db.prepare("INSERT INTO foo(x, y) VALUES (:x, :y)").unwrap();
+        assert_eq!(1, stmt.execute_named_map(&params, UnknownParameters::Ignore).unwrap());
+        match stmt.execute_named_map(&params, UnknownParameters::Error).unwrap_err() {
+            Error::InvalidParameterName(ref name) => assert_eq!(":unused", name),
+            err => panic!("Unexpected error {}", err),
+        }
+
+        params.remove(":unused");
+        assert_eq!(1, stmt.execute_named_map(&params, UnknownParameters::Error).unwrap());
+
+        let count: i32 = db.query_row("SELECT COUNT(*) FROM foo WHERE x = 1 AND y = 'one'",
+                                      &[],
+                                      |r| r.get(0))
+            .unwrap();
+        assert_eq!(2, count);
+    }
+
     #[test]
     fn test_stmt_execute_named() {
         let db = Connection::open_in_memory().unwrap();
@@ -681,6 +1245,208 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_query_map_into() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = "BEGIN;
+                   CREATE TABLE foo(x INTEGER, y TEXT);
+                   INSERT INTO foo VALUES(4, 'hello');
+                   INSERT INTO foo VALUES(3, ', ');
+                   INSERT INTO foo VALUES(2, 'world');
+                   INSERT INTO foo VALUES(1, '!');
+                   END;";
+        db.execute_batch(sql).unwrap();
+
+        let mut stmt = db.prepare("SELECT x, y FROM foo ORDER BY x DESC").unwrap();
+        let mapped: Vec<(i32, String)> = stmt.query_map(&[], |r| (r.get(0), r.get(1)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        let into: Vec<(i32, String)> = stmt.query_map_into(&[], |r| {
+                Ok((try!(r.get_checked(0)), try!(r.get_checked(1))))
+            })
+            .unwrap();
+        assert_eq!(mapped, into);
+
+        match stmt.query_map_into(&[], |r| r.get_checked::<_, i32>(1)).unwrap_err() {
+            Error::InvalidColumnType(1, _) => (),
+            err => panic!("Unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn test_query_matrix() {
+        use types::Value;
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(i INTEGER, f REAL, t TEXT, b BLOB);
+                          INSERT INTO foo VALUES (1, 1.5, 'one', X'01');
+                          INSERT INTO foo VALUES (NULL, NULL, NULL, NULL);")
+            .unwrap();
+
+        let mut stmt = db.prepare("SELECT i, f, t AS text, b FROM foo ORDER BY rowid").unwrap();
+        let (names, rows) = stmt.query_matrix(&[]).unwrap();
+        assert_eq!(vec!["i", "f", "text", "b"], names);
+        assert_eq!(vec![vec![Value::Integer(1),
+                             Value::Real(1.5),
+                             Value::Text("one".to_owned()),
+                             Value::Blob(vec![1])],
+                        vec![Value::Null, Value::Null, Value::Null, Value::Null]],
+                   rows);
+
+        let mut stmt = db.prepare("SELECT i FROM foo WHERE i > ?").unwrap();
+        let (names, rows) = stmt.query_matrix(&[&10]).unwrap();
+        assert_eq!(vec!["i"], names);
+        assert!(rows.is_empty());
+    }
+
+    #[test]
+    fn test_column_values() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER, y TEXT);
+                          INSERT INTO foo VALUES (3, 'a');
+                          INSERT INTO foo VALUES (1, NULL);
+                          INSERT INTO foo VALUES (2, 'c');")
+            .unwrap();
+
+        let mut stmt = db.prepare("SELECT x, y FROM foo ORDER BY x").unwrap();
+        let xs: Vec<i64> = stmt.column_values(&[], 0).unwrap();
+        assert_eq!(vec![1, 2, 3], xs);
+        let ys: Vec<Option<String>> = stmt.column_values(&[], 1).unwrap();
+        assert_eq!(vec![None, Some("c".to_owned()), Some("a".to_owned())], ys);
+
+        match stmt.column_values::<String>(&[], 1).unwrap_err() {
+            Error::InvalidColumnType(1, _) => (),
+            err => panic!("Unexpected error {}", err),
+        }
+        match stmt.column_values::<i64>(&[], 2).unwrap_err() {
+            Error::InvalidColumnIndex(2) => (),
+            err => panic!("Unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn test_execute_returning() {
+        if ::version_number() < 3_035_000 {
+            // RETURNING was added in SQLite 3.35.0.
+            return;
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(id INTEGER PRIMARY KEY, x INTEGER)").unwrap();
+
+        match db.execute("INSERT INTO foo (x) VALUES (1) RETURNING id", &[]).unwrap_err() {
+            Error::ExecuteReturnedResults => (),
+            err => panic!("Unexpected error {}", err),
+        }
+        // The rejected statement was not run.
+        assert_eq!(0, db.query_row("SELECT COUNT(*) FROM foo", &[], |r| r.get::<_, i64>(0))
+                       .unwrap());
+
+        let mut stmt = db.prepare("INSERT INTO foo (x) VALUES (?), (?) RETURNING id, x").unwrap();
+        let rows: Vec<(i64, i64)> = stmt.execute_returning(&[&10, &20],
+                                                           |r| Ok((r.get(0), r.get(1))))
+            .unwrap();
+        assert_eq!(vec![(1, 10), (2, 20)], rows);
+        assert_eq!(2, db.changes());
+
+        let ids: Vec<i64> = db.execute_returning("DELETE FROM foo WHERE x > ? RETURNING id",
+                                                 &[&15],
+                                                 |r| r.get_checked(0))
+            .unwrap();
+        assert_eq!(vec![2], ids);
+        assert_eq!(1, db.query_row("SELECT COUNT(*) FROM foo", &[], |r| r.get::<_, i64>(0))
+                       .unwrap());
+
+        // Statements without RETURNING still run, and return no rows.
+        let none: Vec<i64> = db.execute_returning("UPDATE foo SET x = 2", &[], |r| r.get_checked(0))
+            .unwrap();
+        assert!(none.is_empty());
+        assert_eq!(1, db.changes());
+    }
+
+    #[test]
+    fn test_column_decltype() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER, y VARCHAR(20), z)").unwrap();
+
+        let stmt = db.prepare("SELECT x, y AS why, z, x + 1 FROM foo").unwrap();
+        assert_eq!(Some("INTEGER"), stmt.column_decltype(0));
+        assert_eq!(Some("VARCHAR(20)"), stmt.column_decltype(1));
+        assert_eq!(None, stmt.column_decltype(2));
+        assert_eq!(None, stmt.column_decltype(3));
+        assert_eq!(None, stmt.column_decltype(4));
+    }
+
+    #[test]
+    #[cfg(feature = "column_metadata")]
+    fn test_column_table_and_origin_name() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER, y TEXT)").unwrap();
+
+        let stmt = db.prepare("SELECT f.y AS why, x, 1 FROM foo AS f").unwrap();
+        assert_eq!(Some("foo"), stmt.column_table_name(0));
+        assert_eq!(Some("y"), stmt.column_origin_name(0));
+        assert_eq!(Some("foo"), stmt.column_table_name(1));
+        assert_eq!(Some("x"), stmt.column_origin_name(1));
+        assert_eq!(None, stmt.column_table_name(2));
+        assert_eq!(None, stmt.column_origin_name(2));
+    }
+
+    #[test]
+    #[cfg(feature = "normalize")]
+    fn test_normalized_sql() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(id INTEGER, name TEXT)").unwrap();
+
+        let one = db.prepare("SELECT name FROM foo WHERE id = 1").unwrap();
+        let two = db.prepare("select name  from foo where id=2").unwrap();
+        let normalized = one.normalized_sql().unwrap();
+        assert_eq!(Some(normalized.clone()), two.normalized_sql());
+        assert!(!normalized.contains('1'));
+
+        let other = db.prepare("SELECT id FROM foo WHERE name = 'x'").unwrap();
+        assert!(other.normalized_sql().unwrap() != normalized);
+    }
+
+    #[test]
+    fn test_query_map_into_large() {
+        let db = Connection::open_in_memory().unwrap();
+        let mut stmt = db.prepare("WITH RECURSIVE cnt(x) AS (SELECT 1 UNION ALL SELECT x + 1 \
+                                   FROM cnt LIMIT 100000) SELECT x FROM cnt")
+            .unwrap();
+
+        let values: Vec<i64> = stmt.query_map_into(&[], |r| r.get_checked(0)).unwrap();
+        assert_eq!(100000, values.len());
+        assert_eq!(5000050000, values.iter().sum::<i64>());
+    }
+
+    #[test]
+    fn test_reuse_across_transactions() {
+        use std::mem;
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        let mut select = db.prepare("SELECT x FROM foo ORDER BY x").unwrap();
+        let mut insert = db.prepare("INSERT INTO foo VALUES (?)").unwrap();
+
+        db.execute_batch("BEGIN").unwrap();
+        insert.execute(&[&1]).unwrap();
+        insert.execute(&[&2]).unwrap();
+        {
+            // leave the statement in the middle of its result set
+            let mut rows = select.query(&[]).unwrap();
+            assert_eq!(1i32, rows.next().unwrap().unwrap().get::<_, i32>(0));
+            mem::forget(rows);
+        }
+        db.execute_batch("COMMIT").unwrap();
+
+        db.execute_batch("BEGIN").unwrap();
+        insert.execute(&[&3]).unwrap();
+        let xs: Vec<i32> = select.query_map_into(&[], |r| r.get_checked(0)).unwrap();
+        assert_eq!(vec![1, 2, 3], xs);
+        db.execute_batch("COMMIT").unwrap();
+    }
+
     #[test]
     fn test_unbound_parameters_are_null() {
         let db = Connection::open_in_memory().unwrap();
@@ -785,4 +1551,148 @@ mod test {
         let y: Result<i64> = stmt.query_row(&[&1i32], |r| r.get(0));
         assert_eq!(3i64, y.unwrap());
     }
+
+    #[test]
+    fn test_column_index_all() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE person (id INTEGER, name TEXT, parent INTEGER);
+                          INSERT INTO person VALUES (1, 'alice', NULL);
+                          INSERT INTO person VALUES (2, 'bob', 1);")
+            .unwrap();
+
+        let mut stmt = db.prepare("SELECT c.id, c.name, p.id, p.name FROM person c
+                                   JOIN person p ON c.parent = p.id")
+            .unwrap();
+        assert_eq!(vec![0, 2], stmt.column_index_all("id"));
+        assert_eq!(vec![1, 3], stmt.column_index_all("name"));
+        assert!(stmt.column_index_all("parent").is_empty());
+        assert_eq!(0, stmt.column_index("id").unwrap());
+
+        let ids = stmt.column_index_all("id");
+        let (first, child_id, parent_id): (String, i32, i32) = stmt.query_row(&[], |row| {
+                (row.get("name"), row.get(ids[0]), row.get(ids[1]))
+            })
+            .unwrap();
+        assert_eq!("bob", first);
+        assert_eq!(2, child_id);
+        assert_eq!(1, parent_id);
+    }
+
+    #[test]
+    fn test_estimated_row_count() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (id INTEGER PRIMARY KEY, x INTEGER);
+                          CREATE INDEX foo_x ON foo(x);")
+            .unwrap();
+        {
+            let mut stmt = db.prepare("INSERT INTO foo (x) VALUES (?)").unwrap();
+            for i in 0..1000 {
+                stmt.execute(&[&(i % 10)]).unwrap();
+            }
+        }
+
+        let stmt = db.prepare("SELECT * FROM foo").unwrap();
+        assert_eq!(1000, stmt.estimated_row_count().unwrap());
+        let stmt = db.prepare("SELECT * FROM foo WHERE id = ?").unwrap();
+        assert_eq!(1, stmt.estimated_row_count().unwrap());
+        let stmt = db.prepare("SELECT f.x FROM foo AS f JOIN foo g ON g.id = f.x").unwrap();
+        assert_eq!(1000, stmt.estimated_row_count().unwrap());
+        let stmt = db.prepare("SELECT 1").unwrap();
+        assert_eq!(1, stmt.estimated_row_count().unwrap());
+
+        db.execute_batch("CREATE TABLE bar (k TEXT PRIMARY KEY) WITHOUT ROWID;
+                          INSERT INTO bar VALUES ('a'), ('b'), ('c');")
+            .unwrap();
+        let stmt = db.prepare("SELECT * FROM bar").unwrap();
+        assert_eq!(10, stmt.estimated_row_count().unwrap());
+
+        db.execute_batch("ANALYZE").unwrap();
+        let stmt = db.prepare("SELECT * FROM foo WHERE x = ?").unwrap();
+        let estimate = stmt.estimated_row_count().unwrap();
+        let actual: i64 = db.query_row("SELECT COUNT(*) FROM foo WHERE x = 3", &[], |r| r.get(0))
+            .unwrap();
+        assert!(estimate >= actual / 2 && estimate <= actual * 2,
+                "estimate {} is far from {}",
+                estimate,
+                actual);
+    }
+
+    #[test]
+    fn test_estimated_row_count_names() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE \"my table\" (id INTEGER PRIMARY KEY, x INTEGER);
+                          CREATE TABLE \"CONSTANT\" (x INTEGER);
+                          CREATE TABLE odd (_rowid_ TEXT, rowid TEXT);")
+            .unwrap();
+        {
+            let mut stmt = db.prepare("INSERT INTO \"my table\" (x) VALUES (?)").unwrap();
+            for i in 0..50 {
+                stmt.execute(&[&i]).unwrap();
+            }
+        }
+        db.execute_batch("INSERT INTO \"CONSTANT\" VALUES (1), (2), (3), (4), (5);
+                          INSERT INTO odd VALUES ('a', 'b'), ('c', 'd'), ('e', 'f');")
+            .unwrap();
+
+        let estimate = |sql: &str| db.prepare(sql).unwrap().estimated_row_count().unwrap();
+        assert_eq!(50, estimate("SELECT * FROM \"my table\""));
+        assert_eq!(1, estimate("SELECT * FROM \"my table\" WHERE id = 1"));
+        assert_eq!(50, estimate("SELECT t.x FROM \"my table\" AS t"));
+        assert_eq!(250, estimate("SELECT * FROM \"CONSTANT\" c, \"my table\" t WHERE t.x > c.x"));
+        assert_eq!(5, estimate("SELECT * FROM \"CONSTANT\" c JOIN \"my table\" t ON t.id = c.x"));
+        assert_eq!(5, estimate("SELECT * FROM \"CONSTANT\""));
+        assert_eq!(3, estimate("SELECT * FROM odd"));
+    }
+
+    #[test]
+    fn test_reprepare_after_schema_change() {
+        extern crate tempdir;
+
+        let temp_dir = tempdir::TempDir::new("test_reprepare_after_schema_change").unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db1 = Connection::open(&path).unwrap();
+        let db2 = Connection::open(&path).unwrap();
+        db1.execute_batch("CREATE TABLE foo (x INTEGER)").unwrap();
+
+        let mut insert = db1.prepare_cached("INSERT INTO foo (x) VALUES (?)").unwrap();
+        let mut select = db1.prepare("SELECT * FROM foo").unwrap();
+        insert.execute(&[&1]).unwrap();
+
+        // Change the schema from another connection; both statements are now stale.
+        db2.execute_batch("ALTER TABLE foo ADD COLUMN y INTEGER DEFAULT 2;
+                           CREATE INDEX foo_x ON foo(x);")
+            .unwrap();
+
+        insert.execute(&[&3]).unwrap();
+        let rows: Vec<(i32, i32)> = select.query_map(&[], |r| (r.get(0), r.get(1)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(vec![(1, 2), (3, 2)], rows);
+    }
+
+    #[test]
+    fn test_execute_and_query_boxed() {
+        use types::{ToSql, Value};
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (i INTEGER, r REAL, t TEXT, b BLOB, n)").unwrap();
+
+        let params: Vec<Box<ToSql>> = vec![Box::new(1i64),
+                                           Box::new(2.5f64),
+                                           Box::new("three".to_owned()),
+                                           Box::new(vec![4u8]),
+                                           Box::new(None::<i32>)];
+        let mut stmt = db.prepare("INSERT INTO foo VALUES (?, ?, ?, ?, ?)").unwrap();
+        assert_eq!(1, stmt.execute_boxed(&params).unwrap());
+
+        let mut stmt = db.prepare("SELECT typeof(i), typeof(r), typeof(t), typeof(b), typeof(n)
+                                   FROM foo WHERE i = ? AND t = ?")
+            .unwrap();
+        let params: Vec<Box<ToSql>> = vec![Box::new(Value::Integer(1)), Box::new("three")];
+        let mut rows = stmt.query_boxed(&params).unwrap();
+        let row = rows.next().unwrap().unwrap();
+        let types: Vec<String> = (0..5).map(|i| row.get(i)).collect();
+        assert_eq!(vec!["integer", "real", "text", "blob", "null"], types);
+    }
 }
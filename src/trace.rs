@@ -1,16 +1,33 @@
 //! Tracing and profiling functions. Error and warning log.
 
 use std::os::raw::{c_char, c_int, c_void};
+#[cfg(feature = "trace_v2")]
+use std::os::raw::c_uint;
 use std::ffi::{CStr, CString};
 use std::mem;
 use std::ptr;
 use std::time::Duration;
 
 use super::ffi;
-use {Result, Connection};
+use {Result, Connection, InnerConnection};
+#[cfg(feature = "trace_v2")]
+use TraceCallbacks;
 use error::error_from_sqlite_code;
 
-/// Set up the process-wide SQLite error logging callback.
+/// The type of closure stored by `config_log`.
+pub type LogHandler = Box<Fn(c_int, &str) + Send + Sync>;
+
+// The handler registered by `config_log`, if any; only accessed by `config_log`, which is not
+// threadsafe anyway.
+static mut LOG_HANDLER: *mut LogHandler = 0 as *mut LogHandler;
+
+/// Set up the process-wide SQLite error logging callback. Passing `None` removes the current
+/// callback. The callback is kept alive until it is replaced or removed.
+///
+/// Besides errors, SQLite reports notices and warnings through this log, e.g.
+/// `SQLITE_WARNING_AUTOINDEX` when it creates an automatic index for a query. The callback
+/// receives the (extended) result code and the message.
+///
 /// This function is marked unsafe for two reasons:
 ///
 /// * The function is not threadsafe. No other SQLite calls may be made while
@@ -21,31 +38,47 @@ use error::error_from_sqlite_code;
 ///     * It must be threadsafe if SQLite is used in a multithreaded way.
 ///
 /// cf [The Error And Warning Log](http://sqlite.org/errlog.html).
-pub unsafe fn config_log(callback: Option<fn(c_int, &str)>) -> Result<()> {
+///
+/// # Failure
+///
+/// SQLite can only be configured before it is initialized, which happens when the first
+/// connection is opened. Will return `Err` with `SQLITE_MISUSE` if SQLite has already been
+/// initialized.
+pub unsafe fn config_log<F>(callback: Option<F>) -> Result<()>
+    where F: Fn(c_int, &str) + Send + Sync + 'static
+{
     extern "C" fn log_callback(p_arg: *mut c_void, err: c_int, msg: *const c_char) {
         let c_slice = unsafe { CStr::from_ptr(msg).to_bytes() };
-        let callback: fn(c_int, &str) = unsafe { mem::transmute(p_arg) };
+        let callback: *mut LogHandler = unsafe { mem::transmute(p_arg) };
 
         let s = String::from_utf8_lossy(c_slice);
-        callback(err, &s);
+        unsafe { (*callback)(err, &s) };
     }
 
-    let rc = match callback {
-        Some(f) => {
-            let p_arg: *mut c_void = mem::transmute(f);
-            ffi::sqlite3_config(ffi::SQLITE_CONFIG_LOG,
-                                log_callback as extern "C" fn(_, _, _),
-                                p_arg)
-        }
-        None => {
-            let nullptr: *mut c_void = ptr::null_mut();
-            ffi::sqlite3_config(ffi::SQLITE_CONFIG_LOG, nullptr, nullptr)
-        }
+    let handler: *mut LogHandler = match callback {
+        Some(f) => Box::into_raw(Box::new(Box::new(f) as LogHandler)),
+        None => ptr::null_mut(),
+    };
+    let rc = if handler.is_null() {
+        let nullptr: *mut c_void = ptr::null_mut();
+        ffi::sqlite3_config(ffi::SQLITE_CONFIG_LOG, nullptr, nullptr)
+    } else {
+        let p_arg: *mut c_void = mem::transmute(handler);
+        ffi::sqlite3_config(ffi::SQLITE_CONFIG_LOG,
+                            log_callback as extern "C" fn(_, _, _),
+                            p_arg)
     };
 
     if rc == ffi::SQLITE_OK {
+        if !LOG_HANDLER.is_null() {
+            let _: Box<LogHandler> = Box::from_raw(LOG_HANDLER);
+        }
+        LOG_HANDLER = handler;
         Ok(())
     } else {
+        if !handler.is_null() {
+            let _: Box<LogHandler> = Box::from_raw(handler);
+        }
         Err(error_from_sqlite_code(rc, None))
     }
 }
@@ -58,64 +91,208 @@ pub fn log(err_code: c_int, msg: &str) {
     }
 }
 
+/// The type of closure stored by `Connection::trace`.
+pub type Tracer = Box<FnMut(&str) + Send>;
+/// The type of closure stored by `Connection::profile`.
+pub type Profiler = Box<FnMut(&str, Duration) + Send>;
+
 impl Connection {
     /// Register or clear a callback function that can be used for tracing the execution of SQL
     /// statements.
     ///
     /// Prepared statement placeholders are replaced/logged with their assigned values.
     /// There can only be a single tracer defined for each database connection.
-    /// Setting a new tracer clears the old one.
-    pub fn trace(&mut self, trace_fn: Option<fn(&str)>) {
+    ///
+    /// With the `trace_v2` feature, this uses `sqlite3_trace_v2` and `sqlite3_expanded_sql`
+    /// instead of the deprecated `sqlite3_trace`; statements run by a trigger are then reported
+    /// as SQL comments (e.g., `-- TRIGGER name`) rather than expanded.
+    /// Setting a new tracer clears the old one. The callback is kept alive by the connection
+    /// until it is replaced or cleared or the connection is closed.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::Connection;
+    /// fn log_statements(conn: &mut Connection) {
+    ///     conn.trace(Some(|sql: &str| println!("executing: {}", sql)));
+    /// }
+    /// ```
+    pub fn trace<F>(&mut self, trace_fn: Option<F>)
+        where F: FnMut(&str) + Send + 'static
+    {
+        self.db.borrow_mut().trace(trace_fn.map(|f| Box::new(f) as Tracer));
+    }
+
+    /// Register or clear a callback function that can be used for profiling the execution of SQL
+    /// statements. The callback receives the text of each statement (as originally prepared)
+    /// and the wall-clock time it took to run. With the `trace_v2` feature, this uses
+    /// `sqlite3_trace_v2` instead of the deprecated `sqlite3_profile`.
+    ///
+    /// There can only be a single profiler defined for each database connection.
+    /// Setting a new profiler clears the old one. The callback is kept alive by the connection
+    /// until it is replaced or cleared or the connection is closed.
+    pub fn profile<F>(&mut self, profile_fn: Option<F>)
+        where F: FnMut(&str, Duration) + Send + 'static
+    {
+        self.db.borrow_mut().profile(profile_fn.map(|f| Box::new(f) as Profiler));
+    }
+}
+
+// sqlite3_trace_v2 and sqlite3_expanded_sql require SQLite 3.14.0 or later, so they are not part
+// of the pregenerated bindings.
+#[cfg(feature = "trace_v2")]
+extern "C" {
+    fn sqlite3_trace_v2(db: *mut ffi::sqlite3,
+                        mask: c_uint,
+                        callback: Option<unsafe extern "C" fn(c_uint,
+                                                              *mut c_void,
+                                                              *mut c_void,
+                                                              *mut c_void)
+                                                              -> c_int>,
+                        p_arg: *mut c_void)
+                        -> c_int;
+    fn sqlite3_expanded_sql(stmt: *mut ffi::sqlite3_stmt) -> *mut c_char;
+}
+
+#[cfg(feature = "trace_v2")]
+const SQLITE_TRACE_STMT: c_uint = 0x01;
+#[cfg(feature = "trace_v2")]
+const SQLITE_TRACE_PROFILE: c_uint = 0x02;
+
+fn duration_from_nanos(nanoseconds: u64) -> Duration {
+    const NANOS_PER_SEC: u64 = 1_000_000_000;
+    Duration::new(nanoseconds / NANOS_PER_SEC,
+                  (nanoseconds % NANOS_PER_SEC) as u32)
+}
+
+#[cfg(not(feature = "trace_v2"))]
+impl InnerConnection {
+    fn trace(&mut self, trace_fn: Option<Tracer>) {
         unsafe extern "C" fn trace_callback(p_arg: *mut c_void, z_sql: *const c_char) {
-            let trace_fn: fn(&str) = mem::transmute(p_arg);
+            let trace_fn: *mut Tracer = mem::transmute(p_arg);
             let c_slice = CStr::from_ptr(z_sql).to_bytes();
             let s = String::from_utf8_lossy(c_slice);
-            trace_fn(&s);
+            (*trace_fn)(&s);
         }
 
-        let c = self.db.borrow_mut();
-        match trace_fn {
-            Some(f) => unsafe {
-                ffi::sqlite3_trace(c.db(), Some(trace_callback), mem::transmute(f));
-            },
-            None => unsafe {
-                ffi::sqlite3_trace(c.db(), None, ptr::null_mut());
-            },
+        let db = self.db();
+        self.trace_callbacks.tracer = trace_fn;
+        unsafe {
+            match self.trace_callbacks.tracer {
+                Some(ref mut f) => {
+                    let p_arg: *mut Tracer = f;
+                    ffi::sqlite3_trace(db, Some(trace_callback), mem::transmute(p_arg));
+                }
+                None => {
+                    ffi::sqlite3_trace(db, None, ptr::null_mut());
+                }
+            }
         }
     }
 
-    /// Register or clear a callback function that can be used for profiling the execution of SQL
-    /// statements.
-    ///
-    /// There can only be a single profiler defined for each database connection.
-    /// Setting a new profiler clears the old one.
-    pub fn profile(&mut self, profile_fn: Option<fn(&str, Duration)>) {
+    fn profile(&mut self, profile_fn: Option<Profiler>) {
         unsafe extern "C" fn profile_callback(p_arg: *mut c_void,
                                               z_sql: *const c_char,
                                               nanoseconds: u64) {
-            let profile_fn: fn(&str, Duration) = mem::transmute(p_arg);
+            let profile_fn: *mut Profiler = mem::transmute(p_arg);
             let c_slice = CStr::from_ptr(z_sql).to_bytes();
             let s = String::from_utf8_lossy(c_slice);
-            const NANOS_PER_SEC: u64 = 1_000_000_000;
+            (*profile_fn)(&s, duration_from_nanos(nanoseconds));
+        }
+
+        let db = self.db();
+        self.trace_callbacks.profiler = profile_fn;
+        unsafe {
+            match self.trace_callbacks.profiler {
+                Some(ref mut f) => {
+                    let p_arg: *mut Profiler = f;
+                    ffi::sqlite3_profile(db, Some(profile_callback), mem::transmute(p_arg));
+                }
+                None => {
+                    ffi::sqlite3_profile(db, None, ptr::null_mut());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "trace_v2")]
+impl InnerConnection {
+    fn trace(&mut self, trace_fn: Option<Tracer>) {
+        self.trace_callbacks.tracer = trace_fn;
+        self.register_trace_v2();
+    }
 
-            let duration = Duration::new(nanoseconds / NANOS_PER_SEC,
-                                         (nanoseconds % NANOS_PER_SEC) as u32);
-            profile_fn(&s, duration);
+    fn profile(&mut self, profile_fn: Option<Profiler>) {
+        self.trace_callbacks.profiler = profile_fn;
+        self.register_trace_v2();
+    }
+
+    // (Re-)register the `sqlite3_trace_v2` callback for whichever of the tracer and the profiler
+    // is set.
+    fn register_trace_v2(&mut self) {
+        unsafe extern "C" fn trace_v2_callback(event: c_uint,
+                                               p_arg: *mut c_void,
+                                               p: *mut c_void,
+                                               x: *mut c_void)
+                                               -> c_int {
+            let callbacks: *mut TraceCallbacks = mem::transmute(p_arg);
+            let stmt = p as *mut ffi::sqlite3_stmt;
+            match event {
+                SQLITE_TRACE_STMT => {
+                    if let Some(ref mut f) = (*callbacks).tracer {
+                        // X is the unexpanded SQL of the statement, or a comment such as
+                        // "-- TRIGGER name" when a trigger starts running; only the former is
+                        // expanded. The expanded text is only valid until it is freed.
+                        let unexpanded = x as *const c_char;
+                        let expanded = if CStr::from_ptr(unexpanded).to_bytes().starts_with(b"--") {
+                            ptr::null_mut()
+                        } else {
+                            sqlite3_expanded_sql(stmt)
+                        };
+                        if expanded.is_null() {
+                            f(&String::from_utf8_lossy(CStr::from_ptr(unexpanded).to_bytes()));
+                        } else {
+                            let s = String::from_utf8_lossy(CStr::from_ptr(expanded).to_bytes())
+                                .into_owned();
+                            ffi::sqlite3_free(expanded as *mut c_void);
+                            f(&s);
+                        }
+                    }
+                }
+                SQLITE_TRACE_PROFILE => {
+                    if let Some(ref mut f) = (*callbacks).profiler {
+                        let nanoseconds = *(x as *const i64);
+                        let sql = CStr::from_ptr(ffi::sqlite3_sql(stmt)).to_bytes();
+                        f(&String::from_utf8_lossy(sql), duration_from_nanos(nanoseconds as u64));
+                    }
+                }
+                _ => {}
+            }
+            0
         }
 
-        let c = self.db.borrow_mut();
-        match profile_fn {
-            Some(f) => unsafe {
-                ffi::sqlite3_profile(c.db(), Some(profile_callback), mem::transmute(f))
-            },
-            None => unsafe { ffi::sqlite3_profile(c.db(), None, ptr::null_mut()) },
-        };
+        let mut mask = 0;
+        if self.trace_callbacks.tracer.is_some() {
+            mask |= SQLITE_TRACE_STMT;
+        }
+        if self.trace_callbacks.profiler.is_some() {
+            mask |= SQLITE_TRACE_PROFILE;
+        }
+        unsafe {
+            if mask == 0 {
+                sqlite3_trace_v2(self.db(), 0, None, ptr::null_mut());
+            } else {
+                let p_arg: *mut TraceCallbacks = &mut *self.trace_callbacks;
+                sqlite3_trace_v2(self.db(), mask, Some(trace_v2_callback), mem::transmute(p_arg));
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::sync::Mutex;
+    use std::sync::{Arc, Mutex};
     use std::time::Duration;
 
     use Connection;
@@ -136,7 +313,7 @@ mod test {
             let _ = db.query_row("SELECT ?", &[&1i32], |_| {});
             let _ = db.query_row("SELECT ?", &[&"hello"], |_| {});
         }
-        db.trace(None);
+        db.trace(None::<fn(&str)>);
         {
             let _ = db.query_row("SELECT ?", &[&2i32], |_| {});
             let _ = db.query_row("SELECT ?", &[&"goodbye"], |_| {});
@@ -161,11 +338,49 @@ mod test {
         let mut db = Connection::open_in_memory().unwrap();
         db.profile(Some(profiler));
         db.execute_batch("PRAGMA application_id = 1").unwrap();
-        db.profile(None);
+        db.profile(None::<fn(&str, Duration)>);
         db.execute_batch("PRAGMA application_id = 2").unwrap();
 
         let profiled = PROFILED.lock().unwrap();
         assert_eq!(profiled.len(), 1);
         assert_eq!(profiled[0].0, "PRAGMA application_id = 1");
     }
+
+    #[test]
+    fn test_trace_closure() {
+        let traced = Arc::new(Mutex::new(Vec::new()));
+        let mut db = Connection::open_in_memory().unwrap();
+
+        let tracer_traced = traced.clone();
+        db.trace(Some(move |s: &str| tracer_traced.lock().unwrap().push(s.to_owned())));
+        db.execute("SELECT ?", &[&"captured"]).ok();
+        db.trace(None::<fn(&str)>);
+        db.execute("SELECT ?", &[&"ignored"]).ok();
+
+        assert_eq!(*traced.lock().unwrap(), vec!["SELECT 'captured'".to_owned()]);
+        // Clearing the tracer drops the closure and its captured state.
+        assert_eq!(1, Arc::strong_count(&traced));
+    }
+
+    #[test]
+    #[cfg(feature = "trace_v2")]
+    fn test_trace_trigger() {
+        let traced = Arc::new(Mutex::new(Vec::new()));
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (x INTEGER);
+                          CREATE TABLE log (x INTEGER);
+                          CREATE TRIGGER foo_log AFTER INSERT ON foo BEGIN
+                              INSERT INTO log VALUES (new.x);
+                          END;")
+            .unwrap();
+
+        let tracer_traced = traced.clone();
+        db.trace(Some(move |s: &str| tracer_traced.lock().unwrap().push(s.to_owned())));
+        db.execute("INSERT INTO foo VALUES (?)", &[&7i32]).unwrap();
+        db.trace(None::<fn(&str)>);
+
+        // Newer versions of SQLite also report each statement of the trigger as a comment.
+        let traced = traced.lock().unwrap();
+        assert_eq!(&traced[..2], &["INSERT INTO foo VALUES (7)", "-- TRIGGER foo_log"]);
+    }
 }
@@ -1,5 +1,14 @@
+use std::convert;
 use std::ops::Deref;
-use {Result, Connection};
+use std::os::raw::{c_int, c_void};
+use std::result;
+
+use ffi;
+
+use {Error, Result, Connection};
+#[cfg(feature = "hooks")]
+use hooks::{CommitHook, RollbackHook};
+use types::ToSql;
 
 /// Old name for `TransactionBehavior`. `SqliteTransactionBehavior` is deprecated.
 #[deprecated(since = "0.6.0", note = "Use TransactionBehavior instead")]
@@ -92,6 +101,111 @@ pub struct Savepoint<'conn> {
     committed: bool,
 }
 
+/// The counters behind `Connection::transaction_stats`, together with the closures registered by
+/// `Connection::commit_hook` and `Connection::rollback_hook`.
+///
+/// `InnerConnection` keeps this boxed so that its address can be given to
+/// `sqlite3_commit_hook` and `sqlite3_rollback_hook` for as long as the connection is open.
+pub struct TransactionHooks {
+    committed: u64,
+    rolled_back: u64,
+    // Set while a `Transaction` or `Savepoint` ends a transaction, which it counts itself.
+    ending: bool,
+    #[cfg(feature = "hooks")]
+    pub commit_hook: Option<CommitHook>,
+    #[cfg(feature = "hooks")]
+    pub rollback_hook: Option<RollbackHook>,
+}
+
+impl TransactionHooks {
+    /// Register the hooks that count the transactions committed and rolled back on `db`.
+    pub unsafe fn register(db: *mut ffi::sqlite3) -> Box<TransactionHooks> {
+        unsafe extern "C" fn commit_hook(p_arg: *mut c_void) -> c_int {
+            let hooks = &mut *(p_arg as *mut TransactionHooks);
+            if hooks.veto_commit() {
+                return 1;
+            }
+            if !hooks.ending {
+                hooks.committed += 1;
+            }
+            0
+        }
+
+        unsafe extern "C" fn rollback_hook(p_arg: *mut c_void) {
+            let hooks = &mut *(p_arg as *mut TransactionHooks);
+            hooks.notify_rollback();
+            if !hooks.ending {
+                hooks.rolled_back += 1;
+            }
+        }
+
+        let mut hooks = Box::new(TransactionHooks {
+                                     committed: 0,
+                                     rolled_back: 0,
+                                     ending: false,
+                                     #[cfg(feature = "hooks")]
+                                     commit_hook: None,
+                                     #[cfg(feature = "hooks")]
+                                     rollback_hook: None,
+                                 });
+        let p_arg = &mut *hooks as *mut TransactionHooks as *mut c_void;
+        ffi::sqlite3_commit_hook(db, Some(commit_hook), p_arg);
+        ffi::sqlite3_rollback_hook(db, Some(rollback_hook), p_arg);
+        hooks
+    }
+
+    #[cfg(feature = "hooks")]
+    fn veto_commit(&mut self) -> bool {
+        self.commit_hook.as_mut().map_or(false, |hook| hook())
+    }
+
+    #[cfg(not(feature = "hooks"))]
+    fn veto_commit(&mut self) -> bool {
+        false
+    }
+
+    #[cfg(feature = "hooks")]
+    fn notify_rollback(&mut self) {
+        if let Some(ref mut hook) = self.rollback_hook {
+            hook();
+        }
+    }
+
+    #[cfg(not(feature = "hooks"))]
+    fn notify_rollback(&mut self) {}
+}
+
+// Run `sql`, which commits or rolls back a transaction or savepoint, without the SQLite hooks
+// counting it; the caller updates the counters itself.
+fn end_uncounted(conn: &Connection, sql: &str) -> Result<()> {
+    conn.db.borrow_mut().transaction_hooks.ending = true;
+    let r = conn.execute_batch(sql);
+    conn.db.borrow_mut().transaction_hooks.ending = false;
+    r
+}
+
+// Run `sql`, which commits a transaction or releases a savepoint, and count it. If it fails but
+// leaves the connection in autocommit mode (e.g., because a commit hook vetoed it), the
+// transaction was rolled back instead.
+fn end_with_commit(conn: &Connection, sql: &str) -> Result<()> {
+    let r = end_uncounted(conn, sql);
+    let autocommit = conn.is_autocommit();
+    let mut db = conn.db.borrow_mut();
+    if r.is_ok() {
+        db.transaction_hooks.committed += 1;
+    } else if autocommit {
+        db.transaction_hooks.rolled_back += 1;
+    }
+    r
+}
+
+// Run `sql`, which rolls back a transaction or savepoint, and count it.
+fn end_with_rollback(conn: &Connection, sql: &str) -> Result<()> {
+    try!(end_uncounted(conn, sql));
+    conn.db.borrow_mut().transaction_hooks.rolled_back += 1;
+    Ok(())
+}
+
 impl<'conn> Transaction<'conn> {
     /// Begin a new transaction. Cannot be nested; see `savepoint` for nested transactions.
     pub fn new(conn: &mut Connection, behavior: TransactionBehavior) -> Result<Transaction> {
@@ -162,7 +276,7 @@ impl<'conn> Transaction<'conn> {
 
     fn commit_(&mut self) -> Result<()> {
         self.committed = true;
-        self.conn.execute_batch("COMMIT")
+        end_with_commit(self.conn, "COMMIT")
     }
 
     /// A convenience method which consumes and rolls back a transaction.
@@ -172,7 +286,40 @@ impl<'conn> Transaction<'conn> {
 
     fn rollback_(&mut self) -> Result<()> {
         self.committed = true;
-        self.conn.execute_batch("ROLLBACK")
+        end_with_rollback(self.conn, "ROLLBACK")
+    }
+
+    /// Consumes the transaction and calls `f`, committing the transaction if `f` returns `Ok`
+    /// and rolling it back if it returns `Err`. The result of `f` is returned, unless committing
+    /// or rolling back fails, in which case that error is returned.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # fn do_queries_part_1(_conn: &Connection) -> Result<()> { Ok(()) }
+    /// # fn do_queries_part_2(_conn: &Connection) -> Result<()> { Ok(()) }
+    /// fn perform_queries(conn: &mut Connection) -> Result<()> {
+    ///     try!(conn.transaction()).run(|tx| {
+    ///         try!(do_queries_part_1(tx));
+    ///         do_queries_part_2(tx)
+    ///     })
+    /// }
+    /// ```
+    pub fn run<T, E, F>(mut self, f: F) -> result::Result<T, E>
+        where F: FnOnce(&mut Transaction<'conn>) -> result::Result<T, E>,
+              E: convert::From<Error>
+    {
+        match f(&mut self) {
+            Ok(value) => {
+                try!(self.commit_());
+                Ok(value)
+            }
+            Err(err) => {
+                try!(self.rollback_());
+                Err(err)
+            }
+        }
     }
 
     /// Consumes the transaction, committing or rolling back according to the current setting
@@ -271,8 +418,13 @@ impl<'conn> Savepoint<'conn> {
 
     fn commit_(&mut self) -> Result<()> {
         self.committed = true;
-        self.conn
-            .execute_batch(&format!("RELEASE {}", self.name))
+        end_with_commit(self.conn, &format!("RELEASE {}", self.name))
+    }
+
+    // Release the savepoint after it has been rolled back, which is not counted as a commit.
+    fn release_(&mut self) -> Result<()> {
+        self.committed = true;
+        end_uncounted(self.conn, &format!("RELEASE {}", self.name))
     }
 
     /// A convenience method which rolls back a savepoint.
@@ -282,8 +434,44 @@ impl<'conn> Savepoint<'conn> {
     /// Unlike `Transaction`s, savepoints remain active after they have been rolled back,
     /// and can be rolled back again or committed.
     pub fn rollback(&mut self) -> Result<()> {
-        self.conn
-            .execute_batch(&format!("ROLLBACK TO {}", self.name))
+        end_with_rollback(self.conn, &format!("ROLLBACK TO {}", self.name))
+    }
+
+    /// Consumes the savepoint and calls `f`, releasing the savepoint if `f` returns `Ok` and
+    /// rolling back its changes if it returns `Err`. The result of `f` is returned, unless
+    /// releasing or rolling back fails, in which case that error is returned.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # fn do_queries_part_1(_conn: &Connection) -> Result<()> { Ok(()) }
+    /// # fn do_queries_part_2(_conn: &Connection) -> Result<()> { Ok(()) }
+    /// fn perform_queries(conn: &mut Connection) -> Result<()> {
+    ///     let mut tx = try!(conn.transaction());
+    ///
+    ///     // if do_queries_part_2 fails, only its changes are rolled back
+    ///     try!(do_queries_part_1(&tx));
+    ///     let _ = try!(tx.savepoint()).run(|sp| do_queries_part_2(sp));
+    ///
+    ///     tx.commit()
+    /// }
+    /// ```
+    pub fn run<T, E, F>(mut self, f: F) -> result::Result<T, E>
+        where F: FnOnce(&mut Savepoint<'conn>) -> result::Result<T, E>,
+              E: convert::From<Error>
+    {
+        match f(&mut self) {
+            Ok(value) => {
+                try!(self.commit_());
+                Ok(value)
+            }
+            Err(err) => {
+                try!(self.rollback());
+                try!(self.release_());
+                Err(err)
+            }
+        }
     }
 
     /// Consumes the savepoint, committing or rolling back according to the current setting
@@ -402,11 +590,86 @@ impl Connection {
     pub fn savepoint_with_name<T: Into<String>>(&mut self, name: T) -> Result<Savepoint> {
         Savepoint::with_name(self, name)
     }
+
+    /// Return the number of transactions and savepoints that have been committed and rolled
+    /// back on this connection, in that order.
+    ///
+    /// `Transaction`s and `Savepoint`s are counted whether they are finished explicitly or when
+    /// they are dropped; nested savepoints count separately from the transaction they are in,
+    /// and each `Savepoint::rollback` counts as one rollback. Everything else is counted through
+    /// SQLite's commit and rollback hooks: transactions started with SQL (e.g., `BEGIN` in
+    /// `execute_batch`), statements that write in autocommit mode, and transactions rolled back
+    /// by SQLite itself after an error. A commit vetoed by the hook set with `commit_hook` counts
+    /// as a rollback.
+    ///
+    /// SQLite only calls its commit hook for transactions that wrote to the database, so
+    /// read-only transactions committed with SQL are not counted.
+    pub fn transaction_stats(&self) -> (u64, u64) {
+        let db = self.db.borrow();
+        (db.transaction_hooks.committed, db.transaction_hooks.rolled_back)
+    }
+
+    /// Execute the INSERT statement `sql` once for each element of `rows` inside a single
+    /// transaction, calling `progress` with the number of rows inserted so far after every
+    /// `every_n` rows.
+    ///
+    /// `progress` is also called once all rows have been inserted, unless the final count is a
+    /// multiple of `every_n` (in which case it was just reported). If `every_n` is 0, `progress`
+    /// is only called on completion. On success, the transaction is committed and the number of
+    /// inserted rows is returned.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use rusqlite::types::ToSql;
+    /// fn import_names(conn: &mut Connection, names: &[String]) -> Result<usize> {
+    ///     let rows = names.iter().map(|name| [name as &ToSql]);
+    ///     conn.insert_many_with_progress("INSERT INTO people (name) VALUES (?)",
+    ///                                    rows,
+    ///                                    1000,
+    ///                                    |count| println!("{} rows imported", count))
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be prepared or if any of the inserts fails, in which
+    /// case the transaction is rolled back and none of the rows are inserted.
+    pub fn insert_many_with_progress<'a, I, R, F>(&mut self,
+                                                  sql: &str,
+                                                  rows: I,
+                                                  every_n: usize,
+                                                  mut progress: F)
+                                                  -> Result<usize>
+        where I: IntoIterator<Item = R>,
+              R: AsRef<[&'a ToSql]>,
+              F: FnMut(usize)
+    {
+        let tx = try!(self.transaction());
+        let mut count = 0;
+        {
+            let mut stmt = try!(tx.prepare(sql));
+            for row in rows {
+                try!(stmt.execute(row.as_ref()));
+                count += 1;
+                if every_n != 0 && count % every_n == 0 {
+                    progress(count);
+                }
+            }
+        }
+        try!(tx.commit());
+        if every_n == 0 || count % every_n != 0 {
+            progress(count);
+        }
+        Ok(count)
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use Connection;
+    use {Connection, Error, Result};
+    use types::ToSql;
     use super::DropBehavior;
 
     fn checked_memory_handle() -> Connection {
@@ -548,6 +811,99 @@ mod test {
         assert_current_sum(8, &db);
     }
 
+    #[test]
+    fn test_run() {
+        let mut db = checked_memory_handle();
+        {
+            let mut tx = db.transaction().unwrap();
+            insert(1, &tx);
+
+            let result: Result<()> = tx.savepoint()
+                .unwrap()
+                .run(|sp| {
+                    insert(2, sp);
+                    assert_current_sum(3, sp);
+                    Err(Error::QueryReturnedNoRows)
+                });
+            match result.unwrap_err() {
+                Error::QueryReturnedNoRows => (),
+                err => panic!("Unexpected error {}", err),
+            }
+            assert_current_sum(1, &tx);
+
+            let x = tx.savepoint()
+                .unwrap()
+                .run(|sp| -> Result<i32> {
+                    insert(4, sp);
+                    let nested: Result<()> = try!(sp.savepoint()).run(|sp| {
+                        insert(8, sp);
+                        Err(Error::QueryReturnedNoRows)
+                    });
+                    assert!(nested.is_err());
+                    Ok(4)
+                })
+                .unwrap();
+            assert_eq!(4, x);
+            assert_current_sum(5, &tx);
+            tx.commit().unwrap();
+        }
+        assert_current_sum(5, &db);
+
+        db.transaction()
+            .unwrap()
+            .run(|tx| tx.execute_batch("INSERT INTO foo VALUES(16)"))
+            .unwrap();
+        let result: Result<()> = db.transaction()
+            .unwrap()
+            .run(|tx| {
+                insert(32, tx);
+                Err(Error::QueryReturnedNoRows)
+            });
+        assert!(result.is_err());
+        assert_current_sum(21, &db);
+    }
+
+    #[test]
+    fn test_insert_many_with_progress() {
+        let mut db = checked_memory_handle();
+        let values: Vec<i32> = (0..1000).collect();
+        let mut reports = Vec::new();
+        let count = db.insert_many_with_progress("INSERT INTO foo VALUES(?)",
+                                                 values.iter().map(|x| [x as &ToSql]),
+                                                 250,
+                                                 |n| reports.push(n))
+            .unwrap();
+        assert_eq!(1000, count);
+        assert_eq!(vec![250, 500, 750, 1000], reports);
+
+        let mut reports = Vec::new();
+        db.insert_many_with_progress("INSERT INTO foo VALUES(?)",
+                                     values[..10].iter().map(|x| [x as &ToSql]),
+                                     4,
+                                     |n| reports.push(n))
+            .unwrap();
+        assert_eq!(vec![4, 8, 10], reports);
+
+        let total: i32 = db.query_row("SELECT COUNT(*) FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!(1010, total);
+    }
+
+    #[test]
+    fn test_insert_many_with_progress_rollback() {
+        let mut db = checked_memory_handle();
+        db.execute_batch("CREATE UNIQUE INDEX foo_x ON foo(x)").unwrap();
+        let values = vec![1, 2, 3, 2];
+        let mut reports = Vec::new();
+        assert!(db.insert_many_with_progress("INSERT INTO foo VALUES(?)",
+                                             values.iter().map(|x| [x as &ToSql]),
+                                             1,
+                                             |n| reports.push(n))
+                    .is_err());
+        assert_eq!(vec![1, 2, 3], reports);
+        let total: i32 = db.query_row("SELECT COUNT(*) FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!(0, total);
+    }
+
     fn insert(x: i32, conn: &Connection) {
         conn.execute("INSERT INTO foo VALUES(?)", &[&x]).unwrap();
     }
@@ -557,4 +913,54 @@ mod test {
             .unwrap();
         assert_eq!(x, i);
     }
+
+    #[test]
+    fn test_transaction_stats() {
+        let mut db = checked_memory_handle();
+        // CREATE TABLE in checked_memory_handle is committed in autocommit mode.
+        assert_eq!((1, 0), db.transaction_stats());
+        db.transaction().unwrap().commit().unwrap();
+        {
+            let mut tx = db.transaction().unwrap();
+            tx.set_drop_behavior(DropBehavior::Commit);
+        }
+        db.transaction().unwrap().rollback().unwrap();
+        {
+            let _tx = db.transaction().unwrap();
+        }
+        assert_eq!((3, 2), db.transaction_stats());
+
+        {
+            let mut tx = db.transaction().unwrap();
+            tx.savepoint().unwrap().commit().unwrap();
+            {
+                let _sp = tx.savepoint().unwrap();
+            }
+            tx.commit().unwrap();
+        }
+        assert_eq!((5, 3), db.transaction_stats());
+
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES (1); COMMIT").unwrap();
+        assert_eq!((6, 3), db.transaction_stats());
+        db.execute_batch("INSERT INTO foo VALUES (2)").unwrap();
+        assert_eq!((7, 3), db.transaction_stats());
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES (3); ROLLBACK").unwrap();
+        assert_eq!((7, 4), db.transaction_stats());
+    }
+
+    #[test]
+    fn test_transaction_stats_failed_run() {
+        let mut db = checked_memory_handle();
+        let before = db.transaction_stats();
+        let result: Result<()> = db.savepoint()
+            .unwrap()
+            .run(|sp| {
+                insert(1, sp);
+                Err(Error::QueryReturnedNoRows)
+            });
+        assert!(result.is_err());
+        assert!(db.is_autocommit());
+        let (committed, rolled_back) = db.transaction_stats();
+        assert_eq!((0, 1), (committed - before.0, rolled_back - before.1));
+    }
 }
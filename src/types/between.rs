@@ -0,0 +1,85 @@
+//! Binding ranges as the two parameters of a `BETWEEN` clause.
+use std::ops::{Range, RangeInclusive};
+
+use types::ToSql;
+
+/// The inclusive bounds of a range of integers, for binding to `x BETWEEN ? AND ?`.
+///
+/// `Between` can be created from a `Range<i64>` (whose end is exclusive, so one is subtracted
+/// from it) or a `RangeInclusive<i64>`. An empty range produces bounds that match no rows.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result};
+/// # use rusqlite::types::Between;
+/// fn count_in_range(conn: &Connection) -> Result<i64> {
+///     let range = Between::from(10..=20);
+///     conn.query_row("SELECT COUNT(*) FROM foo WHERE x BETWEEN ? AND ?",
+///                    &range.params(),
+///                    |row| row.get(0))
+/// }
+/// ```
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub struct Between {
+    /// The smallest value in the range.
+    pub start: i64,
+    /// The largest value in the range.
+    pub end: i64,
+}
+
+impl Between {
+    /// The parameters to bind: the start and the end of the range, in that order.
+    pub fn params(&self) -> [&ToSql; 2] {
+        [&self.start, &self.end]
+    }
+}
+
+impl From<Range<i64>> for Between {
+    fn from(range: Range<i64>) -> Between {
+        if range.start >= range.end {
+            // `end - 1` could overflow; use bounds that match nothing instead.
+            Between { start: 1, end: 0 }
+        } else {
+            Between {
+                start: range.start,
+                end: range.end - 1,
+            }
+        }
+    }
+}
+
+impl From<RangeInclusive<i64>> for Between {
+    fn from(range: RangeInclusive<i64>) -> Between {
+        Between {
+            start: *range.start(),
+            end: *range.end(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use Connection;
+    use super::Between;
+
+    fn query_between(db: &Connection, range: Between) -> Vec<i64> {
+        let mut stmt = db.prepare("SELECT x FROM foo WHERE x BETWEEN ? AND ? ORDER BY x").unwrap();
+        let rows = stmt.query_map(&range.params(), |row| row.get(0)).unwrap();
+        rows.map(|r| r.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_between() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (x INTEGER)").unwrap();
+        for i in 1..11 {
+            db.execute("INSERT INTO foo VALUES (?)", &[&i]).unwrap();
+        }
+
+        assert_eq!(vec![3, 4, 5], query_between(&db, Between::from(3..=5)));
+        assert_eq!(vec![3, 4], query_between(&db, Between::from(3..5)));
+        assert!(query_between(&db, Between::from(5..5)).is_empty());
+        assert!(query_between(&db, Between::from(::std::i64::MIN..::std::i64::MIN)).is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+//! `ToSql` and `FromSql` implementation for lists stored as delimited text.
+use std::error::Error;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use Result;
+use types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+
+/// The separator used by `Delimited`.
+pub trait Delimiter {
+    /// The string placed between (and split on) the elements of the list.
+    const SEPARATOR: &'static str;
+}
+
+/// A `Delimiter` that separates elements with a comma.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub struct Comma;
+
+impl Delimiter for Comma {
+    const SEPARATOR: &'static str = ",";
+}
+
+/// A list of values stored as a single TEXT value, with the elements separated by
+/// `D::SEPARATOR` (e.g., `"1,2,3"`).
+///
+/// Each element is read via `FromStr` and written via `Display`; elements are not trimmed or
+/// escaped, so the separator must not appear inside an element. An empty string is an empty
+/// list.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result};
+/// # use rusqlite::types::{Delimited, Delimiter};
+/// struct Pipe;
+///
+/// impl Delimiter for Pipe {
+///     const SEPARATOR: &'static str = "|";
+/// }
+///
+/// fn get_tags(conn: &Connection) -> Result<Vec<String>> {
+///     conn.query_row("SELECT tags FROM posts WHERE id = 1", &[], |row| {
+///         let tags: Delimited<String, Pipe> = row.get(0);
+///         tags.into_inner()
+///     })
+/// }
+/// ```
+#[derive(Clone,Debug,PartialEq)]
+pub struct Delimited<T, D = Comma> {
+    values: Vec<T>,
+    delimiter: PhantomData<D>,
+}
+
+impl<T, D> Delimited<T, D> {
+    /// Wrap `values` so they can be stored as delimited text.
+    pub fn new(values: Vec<T>) -> Delimited<T, D> {
+        Delimited {
+            values: values,
+            delimiter: PhantomData,
+        }
+    }
+
+    /// Unwrap the list of values.
+    pub fn into_inner(self) -> Vec<T> {
+        self.values
+    }
+}
+
+impl<T, D> From<Vec<T>> for Delimited<T, D> {
+    fn from(values: Vec<T>) -> Delimited<T, D> {
+        Delimited::new(values)
+    }
+}
+
+impl<T, D> Deref for Delimited<T, D> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.values
+    }
+}
+
+/// Join the elements with `D::SEPARATOR`.
+impl<T: Display, D: Delimiter> ToSql for Delimited<T, D> {
+    fn to_sql(&self) -> Result<ToSqlOutput> {
+        let parts: Vec<String> = self.values.iter().map(|v| v.to_string()).collect();
+        Ok(ToSqlOutput::from(parts.join(D::SEPARATOR)))
+    }
+}
+
+/// Split text on `D::SEPARATOR` and parse each element.
+impl<T, D: Delimiter> FromSql for Delimited<T, D>
+    where T: FromStr,
+          T::Err: Error + Send + Sync + 'static
+{
+    fn column_result(value: ValueRef) -> FromSqlResult<Self> {
+        let s = try!(value.as_str());
+        if s.is_empty() {
+            return Ok(Delimited::new(Vec::new()));
+        }
+        s.split(D::SEPARATOR)
+            .map(|part| part.parse().map_err(|err| FromSqlError::Other(Box::new(err))))
+            .collect::<FromSqlResult<Vec<T>>>()
+            .map(Delimited::new)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {Connection, Error};
+    use super::{Delimited, Delimiter};
+
+    fn checked_memory_handle() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (t TEXT)").unwrap();
+        db
+    }
+
+    #[test]
+    fn test_delimited_i64() {
+        let db = checked_memory_handle();
+        db.execute("INSERT INTO foo (t) VALUES ('1,2,3')", &[]).unwrap();
+
+        let list: Delimited<i64> = db.query_row("SELECT t FROM foo", &[], |r| r.get(0))
+            .unwrap();
+        assert_eq!(vec![1i64, 2, 3], list.into_inner());
+
+        let list: Delimited<i64> = Delimited::new(vec![4, 5, 6]);
+        db.execute("UPDATE foo SET t = ?", &[&list]).unwrap();
+        let t: String = db.query_row("SELECT t FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!("4,5,6", t);
+    }
+
+    #[test]
+    fn test_delimited_empty() {
+        let db = checked_memory_handle();
+        db.execute("INSERT INTO foo (t) VALUES ('')", &[]).unwrap();
+
+        let list: Delimited<i64> = db.query_row("SELECT t FROM foo", &[], |r| r.get(0))
+            .unwrap();
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_delimited_invalid_element() {
+        let db = checked_memory_handle();
+        db.execute("INSERT INTO foo (t) VALUES ('1,x,3')", &[]).unwrap();
+
+        let result = db.query_row("SELECT t FROM foo",
+                                  &[],
+                                  |r| r.get_checked::<_, Delimited<i64>>(0))
+            .unwrap();
+        match result.unwrap_err() {
+            Error::FromSqlConversionFailure(0, _, _) => (),
+            err => panic!("Unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn test_custom_delimiter() {
+        struct Semicolon;
+
+        impl Delimiter for Semicolon {
+            const SEPARATOR: &'static str = "; ";
+        }
+
+        let db = checked_memory_handle();
+        let list: Delimited<String, Semicolon> = vec!["a".to_owned(), "b c".to_owned()].into();
+        db.execute("INSERT INTO foo (t) VALUES (?)", &[&list]).unwrap();
+
+        let t: String = db.query_row("SELECT t FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!("a; b c", t);
+        let back: Delimited<String, Semicolon> =
+            db.query_row("SELECT t FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!(*list, *back);
+    }
+}
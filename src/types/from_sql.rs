@@ -36,9 +36,9 @@ impl Error for FromSqlError {
     }
 
     #[cfg_attr(feature="clippy", allow(match_same_arms))]
-    fn cause(&self) -> Option<&Error> {
+    fn source(&self) -> Option<&(Error + 'static)> {
         match *self {
-            FromSqlError::Other(ref err) => err.cause(),
+            FromSqlError::Other(ref err) => Some(&**err),
             FromSqlError::InvalidType |
             FromSqlError::OutOfRange(_) => None,
         }
@@ -0,0 +1,208 @@
+//! Newtypes for storing 128-bit integers, which do not fit in SQLite's 64-bit integers.
+//!
+//! Each newtype fixes the storage encoding, so values written by one program can be read back
+//! exactly by another:
+//!
+//! * `I128Blob` and `U128Blob` store a 16-byte BLOB holding the value in big-endian order
+//!   (two's complement for `i128`).
+//! * `I128Text` and `U128Text` store the value as decimal TEXT (e.g., `-42`). They also accept
+//!   INTEGER values, which SQLite produces when decimal text is stored in a column with
+//!   INTEGER or NUMERIC affinity.
+use std::error::Error;
+use std::fmt;
+
+use Result;
+use types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, Value, ValueRef};
+
+/// An `i128` stored as a 16-byte big-endian two's complement BLOB.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct I128Blob(pub i128);
+
+/// A `u128` stored as a 16-byte big-endian BLOB.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct U128Blob(pub u128);
+
+/// An `i128` stored as decimal TEXT.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct I128Text(pub i128);
+
+/// A `u128` stored as decimal TEXT.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct U128Text(pub u128);
+
+/// Error returned (boxed in `FromSqlError::Other`) when a BLOB is not exactly 16 bytes long.
+#[derive(Debug,PartialEq,Eq)]
+pub struct InvalidBlobSize(pub usize);
+
+impl fmt::Display for InvalidBlobSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Expected a 16-byte BLOB, got {} bytes", self.0)
+    }
+}
+
+impl Error for InvalidBlobSize {
+    fn description(&self) -> &str {
+        "invalid 128-bit integer BLOB size"
+    }
+}
+
+fn blob_bytes(value: ValueRef) -> FromSqlResult<[u8; 16]> {
+    let blob = try!(value.as_blob());
+    if blob.len() != 16 {
+        return Err(FromSqlError::Other(Box::new(InvalidBlobSize(blob.len()))));
+    }
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(blob);
+    Ok(bytes)
+}
+
+impl ToSql for I128Blob {
+    fn to_sql(&self) -> Result<ToSqlOutput> {
+        Ok(ToSqlOutput::Owned(Value::Blob(self.0.to_be_bytes().to_vec())))
+    }
+}
+
+impl FromSql for I128Blob {
+    fn column_result(value: ValueRef) -> FromSqlResult<Self> {
+        blob_bytes(value).map(|bytes| I128Blob(i128::from_be_bytes(bytes)))
+    }
+}
+
+impl ToSql for U128Blob {
+    fn to_sql(&self) -> Result<ToSqlOutput> {
+        Ok(ToSqlOutput::Owned(Value::Blob(self.0.to_be_bytes().to_vec())))
+    }
+}
+
+impl FromSql for U128Blob {
+    fn column_result(value: ValueRef) -> FromSqlResult<Self> {
+        blob_bytes(value).map(|bytes| U128Blob(u128::from_be_bytes(bytes)))
+    }
+}
+
+impl ToSql for I128Text {
+    fn to_sql(&self) -> Result<ToSqlOutput> {
+        Ok(ToSqlOutput::Owned(Value::Text(self.0.to_string())))
+    }
+}
+
+impl FromSql for I128Text {
+    fn column_result(value: ValueRef) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Integer(i) => Ok(I128Text(i as i128)),
+            ValueRef::Text(s) => {
+                s.parse().map(I128Text).map_err(|err| FromSqlError::Other(Box::new(err)))
+            }
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ToSql for U128Text {
+    fn to_sql(&self) -> Result<ToSqlOutput> {
+        Ok(ToSqlOutput::Owned(Value::Text(self.0.to_string())))
+    }
+}
+
+impl FromSql for U128Text {
+    fn column_result(value: ValueRef) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Integer(i) if i < 0 => Err(FromSqlError::OutOfRange(i)),
+            ValueRef::Integer(i) => Ok(U128Text(i as u128)),
+            ValueRef::Text(s) => {
+                s.parse().map(U128Text).map_err(|err| FromSqlError::Other(Box::new(err)))
+            }
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {Connection, Error};
+    use types::{FromSql, FromSqlError, ValueRef};
+    use super::{I128Blob, I128Text, InvalidBlobSize, U128Blob, U128Text};
+
+    fn checked_memory_handle() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (b BLOB, t TEXT, i INTEGER)").unwrap();
+        db
+    }
+
+    #[test]
+    fn test_blob_round_trip() {
+        let db = checked_memory_handle();
+        for &v in &[i128::min_value(), -1, 0, 1, i128::max_value()] {
+            db.execute("INSERT INTO foo (b) VALUES (?)", &[&I128Blob(v)]).unwrap();
+            let read: I128Blob = db.query_row("SELECT b FROM foo", &[], |r| r.get(0)).unwrap();
+            assert_eq!(v, read.0);
+            db.execute("DELETE FROM foo", &[]).unwrap();
+        }
+
+        db.execute("INSERT INTO foo (b) VALUES (?)", &[&U128Blob(u128::max_value())]).unwrap();
+        let read: U128Blob = db.query_row("SELECT b FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!(u128::max_value(), read.0);
+    }
+
+    #[test]
+    fn test_blob_encoding() {
+        let db = checked_memory_handle();
+        db.execute("INSERT INTO foo (b) VALUES (?)", &[&I128Blob(-2)]).unwrap();
+        let hex: String = db.query_row("SELECT hex(b) FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFE", hex);
+
+        let err = db.query_row("SELECT X'0102'", &[], |r| r.get_checked::<_, I128Blob>(0))
+            .unwrap()
+            .unwrap_err();
+        match err {
+            Error::FromSqlConversionFailure(0, _, err) => {
+                assert_eq!(Some(&InvalidBlobSize(2)), err.downcast_ref::<InvalidBlobSize>())
+            }
+            err => panic!("Unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let db = checked_memory_handle();
+        let big = I128Text(-170141183460469231731687303715884105728);
+        db.execute("INSERT INTO foo (t) VALUES (?)", &[&big]).unwrap();
+        let text: String = db.query_row("SELECT t FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!("-170141183460469231731687303715884105728", text);
+        let read: I128Text = db.query_row("SELECT t FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!(big, read);
+
+        // Small values are converted to integers by INTEGER affinity.
+        db.execute("INSERT INTO foo (i) VALUES (?)", &[&U128Text(42)]).unwrap();
+        let read: U128Text = db.query_row("SELECT i FROM foo WHERE i IS NOT NULL",
+                                          &[],
+                                          |r| r.get(0))
+            .unwrap();
+        assert_eq!(U128Text(42), read);
+    }
+
+    #[test]
+    fn test_text_out_of_range() {
+        let db = checked_memory_handle();
+        let err = db.query_row("SELECT -1", &[], |r| r.get_checked::<_, U128Text>(0))
+            .unwrap()
+            .unwrap_err();
+        match err {
+            Error::IntegralValueOutOfRange(0, -1) => (),
+            err => panic!("Unexpected error {}", err),
+        }
+
+        let err = db.query_row("SELECT '-1'", &[], |r| r.get_checked::<_, U128Text>(0))
+            .unwrap()
+            .unwrap_err();
+        match err {
+            Error::FromSqlConversionFailure(0, _, _) => (),
+            err => panic!("Unexpected error {}", err),
+        }
+
+        match U128Text::column_result(ValueRef::Real(1.0)) {
+            Err(FromSqlError::InvalidType) => (),
+            _ => panic!("REAL should not convert to U128Text"),
+        }
+    }
+}
@@ -47,6 +47,9 @@
 //! # fn main() {}
 //! ```
 //!
+//! 128-bit integers do not fit in SQLite's integers; the `I128Blob`, `U128Blob`, `I128Text` and
+//! `U128Text` newtypes store them as 16-byte big-endian BLOBs or as decimal TEXT.
+//!
 //! `ToSql` and `FromSql` are also implemented for `Option<T>` where `T` implements `ToSql` or
 //! `FromSql` for the cases where you want to know if a value was NULL (which gets translated to
 //! `None`).
@@ -55,6 +58,9 @@ pub use self::from_sql::{FromSql, FromSqlError, FromSqlResult};
 pub use self::to_sql::{ToSql, ToSqlOutput};
 pub use self::value::Value;
 pub use self::value_ref::ValueRef;
+pub use self::delimited::{Comma, Delimited, Delimiter};
+pub use self::between::Between;
+pub use self::int128::{I128Blob, I128Text, InvalidBlobSize, U128Blob, U128Text};
 
 use std::fmt;
 
@@ -63,6 +69,9 @@ mod value_ref;
 mod from_sql;
 mod to_sql;
 mod time;
+mod delimited;
+mod between;
+mod int128;
 #[cfg(feature = "chrono")]
 mod chrono;
 #[cfg(feature = "serde_json")]
@@ -188,6 +197,27 @@ mod test {
                        .unwrap());
     }
 
+    #[test]
+    fn test_value_storage_class() {
+        let db = checked_memory_handle();
+
+        db.execute("INSERT INTO foo(i, f) VALUES (?, ?)",
+                     &[&Value::Integer(1), &Value::Real(1.0)])
+            .unwrap();
+        db.execute("INSERT INTO foo(i, f) VALUES (1, 1)", &[]).unwrap();
+
+        let mut stmt = db.prepare("SELECT i, f FROM foo").unwrap();
+        let rows: Vec<(Value, Value)> = stmt.query_map(&[], |r| (r.get(0), r.get(1)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        // Each column's affinity decides the storage class: values in the INTEGER column are
+        // read back as Integer and values in the REAL column as Real, whatever was inserted.
+        assert_eq!(vec![(Value::Integer(1), Value::Real(1.0)),
+                        (Value::Integer(1), Value::Real(1.0))],
+                   rows);
+    }
+
     #[test]
     fn test_option() {
         let db = checked_memory_handle();
@@ -23,11 +23,14 @@ impl<'a, T: ?Sized> From<&'a T> for ToSqlOutput<'a>
     }
 }
 
-impl<'a, T: Into<Value>> From<T> for ToSqlOutput<'a> {
-    fn from(t: T) -> Self {
+macro_rules! tmp_owned(($($t:ty),*) => ($(
+impl<'a> From<$t> for ToSqlOutput<'a> {
+    fn from(t: $t) -> Self {
         ToSqlOutput::Owned(t.into())
     }
 }
+)*));
+tmp_owned!(Null, bool, i8, i16, i32, i64, isize, u8, u16, u32, f64, String, Vec<u8>, Value);
 
 impl<'a> ToSql for ToSqlOutput<'a> {
     fn to_sql(&self) -> Result<ToSqlOutput> {
@@ -0,0 +1,654 @@
+//! Register instrumented [VFS](https://www.sqlite.org/vfs.html)es that wrap an existing one.
+//!
+//! A VFS registered with `register_vfs` forwards every operation to an underlying VFS (e.g., the
+//! default `"unix"` or `"win32"` VFS), but first reports file operations to a Rust closure. The
+//! closure can count or log I/O, and can inject faults by making operations fail. Connections
+//! use the VFS when they are opened with `Connection::open_with_flags_and_vfs`, or by default
+//! if it was registered as the default VFS.
+//!
+//! ```rust
+//! # use rusqlite::{Connection, OpenFlags, Result};
+//! # use rusqlite::vfs::{self, VfsEvent};
+//! # use std::sync::Arc;
+//! # use std::sync::atomic::{AtomicUsize, Ordering};
+//! fn count_writes(path: &str) -> Result<usize> {
+//!     let writes = Arc::new(AtomicUsize::new(0));
+//!     let counter = writes.clone();
+//!     try!(vfs::register_vfs("counting", None, false, move |event| {
+//!         if let VfsEvent::Write { .. } = *event {
+//!             counter.fetch_add(1, Ordering::SeqCst);
+//!         }
+//!         true
+//!     }));
+//!
+//!     let db = try!(Connection::open_with_flags_and_vfs(path, OpenFlags::default(), "counting"));
+//!     try!(db.execute_batch("CREATE TABLE foo(x INTEGER)"));
+//!     Ok(writes.load(Ordering::SeqCst))
+//! }
+//! ```
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+use ffi;
+
+use {Error, Result, str_to_cstring};
+use error::error_from_sqlite_code;
+
+/// A file operation reported to the closure of a VFS registered with `register_vfs`.
+///
+/// `path` is the name of the file the operation applies to, or `None` for temporary files
+/// that SQLite opens without a name.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum VfsEvent<'a> {
+    /// A file is being opened.
+    Open { path: Option<&'a str> },
+    /// A file (e.g., a rollback journal) is being deleted.
+    Delete { path: &'a str },
+    /// `amount` bytes are being read at `offset`.
+    Read {
+        path: Option<&'a str>,
+        amount: usize,
+        offset: i64,
+    },
+    /// `amount` bytes are being written at `offset`.
+    Write {
+        path: Option<&'a str>,
+        amount: usize,
+        offset: i64,
+    },
+    /// A file is being truncated to `size` bytes.
+    Truncate { path: Option<&'a str>, size: i64 },
+    /// A file is being flushed to persistent storage.
+    Sync { path: Option<&'a str> },
+}
+
+/// The type of closure stored by `register_vfs`.
+pub type VfsHook = Box<Fn(&VfsEvent) -> bool + Send + Sync>;
+
+/// Register a VFS named `name` that wraps the VFS named `base` (or the default VFS if `base`
+/// is `None`), calling `hook` before each file operation listed in `VfsEvent`.
+///
+/// If `hook` returns `true`, the operation is passed on to the underlying VFS. If it returns
+/// `false`, the operation fails without being attempted, with `SQLITE_CANTOPEN` for
+/// `VfsEvent::Open` and the matching `SQLITE_IOERR_*` code otherwise.
+///
+/// If `make_default` is `true`, the new VFS becomes the default VFS, used by every connection
+/// opened afterwards without an explicit VFS. Registered VFSes (and their closures) live for
+/// the rest of the process; there is no way to unregister them, since connections may still be
+/// using them. Registering a VFS initializes SQLite, so process-wide configuration (see the
+/// `config` module) must happen first.
+///
+/// Write-ahead logging works through the wrapped VFS, but memory-mapped I/O is turned off for
+/// its connections (all reads and writes are reported to `hook`).
+///
+/// # Failure
+///
+/// Will return `Err` if `name` or `base` cannot be converted to a C-compatible string, if a
+/// VFS named `name` is already registered, if no VFS named `base` is registered, or if the
+/// underlying SQLite call fails.
+pub fn register_vfs<F>(name: &str, base: Option<&str>, make_default: bool, hook: F) -> Result<()>
+    where F: Fn(&VfsEvent) -> bool + Send + Sync + 'static
+{
+    let c_name = try!(str_to_cstring(name));
+    let base_vfs = match base {
+        Some(base) => {
+            let c_base = try!(str_to_cstring(base));
+            unsafe { ffi::sqlite3_vfs_find(c_base.as_ptr()) }
+        }
+        None => unsafe { ffi::sqlite3_vfs_find(ptr::null()) },
+    };
+    if base_vfs.is_null() {
+        return Err(misuse(format!("no VFS named {:?} is registered", base.unwrap_or(""))));
+    }
+    if !unsafe { ffi::sqlite3_vfs_find(c_name.as_ptr()) }.is_null() {
+        return Err(misuse(format!("a VFS named {:?} is already registered", name)));
+    }
+
+    unsafe {
+        let state: *mut VfsState = Box::into_raw(Box::new(VfsState {
+                                                              vfs: *base_vfs,
+                                                              io_methods: io_methods(),
+                                                              base: base_vfs,
+                                                              name: c_name,
+                                                              hook: Box::new(hook),
+                                                          }));
+        let vfs = &mut (*state).vfs;
+        // Memory-mapped I/O (version 3) would bypass xRead and xWrite, and later versions
+        // add system call hooks that make no sense to wrap.
+        vfs.iVersion = if (*base_vfs).iVersion < 2 { 1 } else { 2 };
+        vfs.szOsFile = (mem::size_of::<VfsFile>() as c_int) + (*base_vfs).szOsFile;
+        vfs.pNext = ptr::null_mut();
+        vfs.zName = (*state).name.as_ptr();
+        vfs.pAppData = state as *mut c_void;
+        vfs.xOpen = Some(x_open);
+        vfs.xDelete = Some(x_delete);
+        vfs.xAccess = Some(x_access);
+        vfs.xFullPathname = Some(x_full_pathname);
+        vfs.xDlOpen = Some(x_dl_open);
+        vfs.xDlError = Some(x_dl_error);
+        vfs.xDlSym = Some(x_dl_sym);
+        vfs.xDlClose = Some(x_dl_close);
+        vfs.xRandomness = Some(x_randomness);
+        vfs.xSleep = Some(x_sleep);
+        vfs.xCurrentTime = Some(x_current_time);
+        vfs.xGetLastError = Some(x_get_last_error);
+        vfs.xCurrentTimeInt64 = Some(x_current_time_int64);
+        vfs.xSetSystemCall = None;
+        vfs.xGetSystemCall = None;
+        vfs.xNextSystemCall = None;
+
+        let r = ffi::sqlite3_vfs_register(vfs, make_default as c_int);
+        if r != ffi::SQLITE_OK {
+            drop(Box::from_raw(state));
+            return Err(error_from_sqlite_code(r, None));
+        }
+    }
+    Ok(())
+}
+
+fn misuse(msg: String) -> Error {
+    Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_MISUSE), Some(msg))
+}
+
+// The registered VFS, followed by everything its methods need. `vfs.pAppData` points back to
+// this struct.
+struct VfsState {
+    vfs: ffi::sqlite3_vfs,
+    io_methods: ffi::sqlite3_io_methods,
+    base: *mut ffi::sqlite3_vfs,
+    name: ::std::ffi::CString,
+    hook: VfsHook,
+}
+
+// The file handle SQLite allocates for the wrapping VFS (`szOsFile` bytes). The file handle of
+// the underlying VFS is stored directly after it.
+#[repr(C)]
+struct VfsFile {
+    file: ffi::sqlite3_file,
+    state: *const VfsState,
+    path: Option<String>,
+    real: *mut ffi::sqlite3_file,
+}
+
+unsafe fn state<'a>(vfs: *mut ffi::sqlite3_vfs) -> &'a VfsState {
+    &*((*vfs).pAppData as *const VfsState)
+}
+
+unsafe fn opt_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        None
+    } else {
+        CStr::from_ptr(s).to_str().ok()
+    }
+}
+
+// The methods of the underlying file handle of `file`.
+unsafe fn real(file: *mut ffi::sqlite3_file) -> (*mut ffi::sqlite3_file, ffi::sqlite3_io_methods) {
+    let real = (*(file as *mut VfsFile)).real;
+    (real, *(*real).pMethods)
+}
+
+// Report `event` (built from the path of `file`) to the hook; returns `false` if the operation
+// should fail.
+unsafe fn allow<F>(file: *mut ffi::sqlite3_file, event: F) -> bool
+    where F: FnOnce(Option<&str>) -> VfsEvent
+{
+    let file = file as *mut VfsFile;
+    let path = (*file).path.as_ref().map(|p| &p[..]);
+    ((*(*file).state).hook)(&event(path))
+}
+
+unsafe extern "C" fn x_open(vfs: *mut ffi::sqlite3_vfs,
+                            z_name: *const c_char,
+                            file: *mut ffi::sqlite3_file,
+                            flags: c_int,
+                            p_out_flags: *mut c_int)
+                            -> c_int {
+    let state = state(vfs);
+    let path = opt_str(z_name);
+    let wrapped = file as *mut VfsFile;
+    (*wrapped).file.pMethods = ptr::null();
+    if !(state.hook)(&VfsEvent::Open { path: path }) {
+        return ffi::SQLITE_CANTOPEN;
+    }
+
+    let real = (file as *mut u8).offset(mem::size_of::<VfsFile>() as isize) as
+               *mut ffi::sqlite3_file;
+    let r = match (*state.base).xOpen {
+        Some(f) => f(state.base, z_name, real, flags, p_out_flags),
+        None => ffi::SQLITE_CANTOPEN,
+    };
+    // SQLite calls xClose (which must close the underlying file) whenever xOpen sets
+    // pMethods, even if it fails.
+    if !(*real).pMethods.is_null() {
+        ptr::write(&mut (*wrapped).state, state);
+        ptr::write(&mut (*wrapped).path, path.map(String::from));
+        ptr::write(&mut (*wrapped).real, real);
+        (*wrapped).file.pMethods = &state.io_methods;
+    }
+    r
+}
+
+unsafe extern "C" fn x_delete(vfs: *mut ffi::sqlite3_vfs,
+                              z_name: *const c_char,
+                              sync_dir: c_int)
+                              -> c_int {
+    let state = state(vfs);
+    let path = opt_str(z_name).unwrap_or("");
+    if !(state.hook)(&VfsEvent::Delete { path: path }) {
+        return ffi::SQLITE_IOERR_DELETE;
+    }
+    match (*state.base).xDelete {
+        Some(f) => f(state.base, z_name, sync_dir),
+        None => ffi::SQLITE_IOERR_DELETE,
+    }
+}
+
+unsafe extern "C" fn x_access(vfs: *mut ffi::sqlite3_vfs,
+                              z_name: *const c_char,
+                              flags: c_int,
+                              p_res_out: *mut c_int)
+                              -> c_int {
+    let base = state(vfs).base;
+    match (*base).xAccess {
+        Some(f) => f(base, z_name, flags, p_res_out),
+        None => ffi::SQLITE_IOERR,
+    }
+}
+
+unsafe extern "C" fn x_full_pathname(vfs: *mut ffi::sqlite3_vfs,
+                                     z_name: *const c_char,
+                                     n_out: c_int,
+                                     z_out: *mut c_char)
+                                     -> c_int {
+    let base = state(vfs).base;
+    match (*base).xFullPathname {
+        Some(f) => f(base, z_name, n_out, z_out),
+        None => ffi::SQLITE_IOERR,
+    }
+}
+
+unsafe extern "C" fn x_dl_open(vfs: *mut ffi::sqlite3_vfs, z_filename: *const c_char) -> *mut c_void {
+    let base = state(vfs).base;
+    match (*base).xDlOpen {
+        Some(f) => f(base, z_filename),
+        None => ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn x_dl_error(vfs: *mut ffi::sqlite3_vfs, n_byte: c_int, z_err_msg: *mut c_char) {
+    let base = state(vfs).base;
+    if let Some(f) = (*base).xDlError {
+        f(base, n_byte, z_err_msg)
+    }
+}
+
+unsafe extern "C" fn x_dl_sym(vfs: *mut ffi::sqlite3_vfs,
+                              handle: *mut c_void,
+                              z_symbol: *const c_char)
+                              -> Option<unsafe extern "C" fn()> {
+    let base = state(vfs).base;
+    match (*base).xDlSym {
+        Some(f) => f(base, handle, z_symbol),
+        None => None,
+    }
+}
+
+unsafe extern "C" fn x_dl_close(vfs: *mut ffi::sqlite3_vfs, handle: *mut c_void) {
+    let base = state(vfs).base;
+    if let Some(f) = (*base).xDlClose {
+        f(base, handle)
+    }
+}
+
+unsafe extern "C" fn x_randomness(vfs: *mut ffi::sqlite3_vfs,
+                                  n_byte: c_int,
+                                  z_out: *mut c_char)
+                                  -> c_int {
+    let base = state(vfs).base;
+    match (*base).xRandomness {
+        Some(f) => f(base, n_byte, z_out),
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn x_sleep(vfs: *mut ffi::sqlite3_vfs, microseconds: c_int) -> c_int {
+    let base = state(vfs).base;
+    match (*base).xSleep {
+        Some(f) => f(base, microseconds),
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn x_current_time(vfs: *mut ffi::sqlite3_vfs, p_time: *mut f64) -> c_int {
+    let base = state(vfs).base;
+    match (*base).xCurrentTime {
+        Some(f) => f(base, p_time),
+        None => ffi::SQLITE_ERROR,
+    }
+}
+
+unsafe extern "C" fn x_get_last_error(vfs: *mut ffi::sqlite3_vfs,
+                                      n_byte: c_int,
+                                      z_err_msg: *mut c_char)
+                                      -> c_int {
+    let base = state(vfs).base;
+    match (*base).xGetLastError {
+        Some(f) => f(base, n_byte, z_err_msg),
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn x_current_time_int64(vfs: *mut ffi::sqlite3_vfs,
+                                          p_time: *mut ffi::sqlite3_int64)
+                                          -> c_int {
+    let base = state(vfs).base;
+    match (*base).xCurrentTimeInt64 {
+        Some(f) if (*base).iVersion >= 2 => f(base, p_time),
+        _ => ffi::SQLITE_ERROR,
+    }
+}
+
+// The methods of the wrapping file handles. The shared memory methods (version 2) are needed
+// for WAL mode.
+fn io_methods() -> ffi::sqlite3_io_methods {
+    let mut methods: ffi::sqlite3_io_methods = unsafe { mem::zeroed() };
+    methods.iVersion = 2;
+    methods.xClose = Some(x_close);
+    methods.xRead = Some(x_read);
+    methods.xWrite = Some(x_write);
+    methods.xTruncate = Some(x_truncate);
+    methods.xSync = Some(x_sync);
+    methods.xFileSize = Some(x_file_size);
+    methods.xLock = Some(x_lock);
+    methods.xUnlock = Some(x_unlock);
+    methods.xCheckReservedLock = Some(x_check_reserved_lock);
+    methods.xFileControl = Some(x_file_control);
+    methods.xSectorSize = Some(x_sector_size);
+    methods.xDeviceCharacteristics = Some(x_device_characteristics);
+    methods.xShmMap = Some(x_shm_map);
+    methods.xShmLock = Some(x_shm_lock);
+    methods.xShmBarrier = Some(x_shm_barrier);
+    methods.xShmUnmap = Some(x_shm_unmap);
+    methods
+}
+
+unsafe extern "C" fn x_close(file: *mut ffi::sqlite3_file) -> c_int {
+    let (real, methods) = real(file);
+    let r = match methods.xClose {
+        Some(f) => f(real),
+        None => ffi::SQLITE_OK,
+    };
+    drop(ptr::read(&(*(file as *mut VfsFile)).path));
+    (*file).pMethods = ptr::null();
+    r
+}
+
+unsafe extern "C" fn x_read(file: *mut ffi::sqlite3_file,
+                            buf: *mut c_void,
+                            amount: c_int,
+                            offset: ffi::sqlite3_int64)
+                            -> c_int {
+    if !allow(file, |path| {
+        VfsEvent::Read {
+            path: path,
+            amount: amount as usize,
+            offset: offset,
+        }
+    }) {
+        return ffi::SQLITE_IOERR_READ;
+    }
+    let (real, methods) = real(file);
+    match methods.xRead {
+        Some(f) => f(real, buf, amount, offset),
+        None => ffi::SQLITE_IOERR_READ,
+    }
+}
+
+unsafe extern "C" fn x_write(file: *mut ffi::sqlite3_file,
+                             buf: *const c_void,
+                             amount: c_int,
+                             offset: ffi::sqlite3_int64)
+                             -> c_int {
+    if !allow(file, |path| {
+        VfsEvent::Write {
+            path: path,
+            amount: amount as usize,
+            offset: offset,
+        }
+    }) {
+        return ffi::SQLITE_IOERR_WRITE;
+    }
+    let (real, methods) = real(file);
+    match methods.xWrite {
+        Some(f) => f(real, buf, amount, offset),
+        None => ffi::SQLITE_IOERR_WRITE,
+    }
+}
+
+unsafe extern "C" fn x_truncate(file: *mut ffi::sqlite3_file, size: ffi::sqlite3_int64) -> c_int {
+    if !allow(file, |path| VfsEvent::Truncate { path: path, size: size }) {
+        return ffi::SQLITE_IOERR_TRUNCATE;
+    }
+    let (real, methods) = real(file);
+    match methods.xTruncate {
+        Some(f) => f(real, size),
+        None => ffi::SQLITE_IOERR_TRUNCATE,
+    }
+}
+
+unsafe extern "C" fn x_sync(file: *mut ffi::sqlite3_file, flags: c_int) -> c_int {
+    if !allow(file, |path| VfsEvent::Sync { path: path }) {
+        return ffi::SQLITE_IOERR_FSYNC;
+    }
+    let (real, methods) = real(file);
+    match methods.xSync {
+        Some(f) => f(real, flags),
+        None => ffi::SQLITE_IOERR_FSYNC,
+    }
+}
+
+unsafe extern "C" fn x_file_size(file: *mut ffi::sqlite3_file,
+                                 p_size: *mut ffi::sqlite3_int64)
+                                 -> c_int {
+    let (real, methods) = real(file);
+    match methods.xFileSize {
+        Some(f) => f(real, p_size),
+        None => ffi::SQLITE_IOERR_FSTAT,
+    }
+}
+
+unsafe extern "C" fn x_lock(file: *mut ffi::sqlite3_file, lock: c_int) -> c_int {
+    let (real, methods) = real(file);
+    match methods.xLock {
+        Some(f) => f(real, lock),
+        None => ffi::SQLITE_IOERR_LOCK,
+    }
+}
+
+unsafe extern "C" fn x_unlock(file: *mut ffi::sqlite3_file, lock: c_int) -> c_int {
+    let (real, methods) = real(file);
+    match methods.xUnlock {
+        Some(f) => f(real, lock),
+        None => ffi::SQLITE_IOERR_UNLOCK,
+    }
+}
+
+unsafe extern "C" fn x_check_reserved_lock(file: *mut ffi::sqlite3_file,
+                                           p_res_out: *mut c_int)
+                                           -> c_int {
+    let (real, methods) = real(file);
+    match methods.xCheckReservedLock {
+        Some(f) => f(real, p_res_out),
+        None => ffi::SQLITE_IOERR_CHECKRESERVEDLOCK,
+    }
+}
+
+unsafe extern "C" fn x_file_control(file: *mut ffi::sqlite3_file,
+                                    op: c_int,
+                                    p_arg: *mut c_void)
+                                    -> c_int {
+    let (real, methods) = real(file);
+    match methods.xFileControl {
+        Some(f) => f(real, op, p_arg),
+        None => ffi::SQLITE_NOTFOUND,
+    }
+}
+
+unsafe extern "C" fn x_sector_size(file: *mut ffi::sqlite3_file) -> c_int {
+    let (real, methods) = real(file);
+    match methods.xSectorSize {
+        Some(f) => f(real),
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn x_device_characteristics(file: *mut ffi::sqlite3_file) -> c_int {
+    let (real, methods) = real(file);
+    match methods.xDeviceCharacteristics {
+        Some(f) => f(real),
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn x_shm_map(file: *mut ffi::sqlite3_file,
+                               i_pg: c_int,
+                               pgsz: c_int,
+                               extend: c_int,
+                               pp: *mut *mut c_void)
+                               -> c_int {
+    let (real, methods) = real(file);
+    match methods.xShmMap {
+        Some(f) if methods.iVersion >= 2 => f(real, i_pg, pgsz, extend, pp),
+        _ => ffi::SQLITE_IOERR_SHMMAP,
+    }
+}
+
+unsafe extern "C" fn x_shm_lock(file: *mut ffi::sqlite3_file,
+                                offset: c_int,
+                                n: c_int,
+                                flags: c_int)
+                                -> c_int {
+    let (real, methods) = real(file);
+    match methods.xShmLock {
+        Some(f) if methods.iVersion >= 2 => f(real, offset, n, flags),
+        _ => ffi::SQLITE_IOERR_SHMLOCK,
+    }
+}
+
+unsafe extern "C" fn x_shm_barrier(file: *mut ffi::sqlite3_file) {
+    let (real, methods) = real(file);
+    match methods.xShmBarrier {
+        Some(f) if methods.iVersion >= 2 => f(real),
+        _ => (),
+    }
+}
+
+unsafe extern "C" fn x_shm_unmap(file: *mut ffi::sqlite3_file, delete_flag: c_int) -> c_int {
+    let (real, methods) = real(file);
+    match methods.xShmUnmap {
+        Some(f) if methods.iVersion >= 2 => f(real, delete_flag),
+        _ => ffi::SQLITE_OK,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate tempdir;
+
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use self::tempdir::TempDir;
+    use {Connection, Error, ErrorCode, OpenFlags};
+    use super::{register_vfs, VfsEvent};
+
+    #[derive(Debug,PartialEq)]
+    enum Op {
+        Open,
+        Read,
+        Write,
+        Sync,
+    }
+
+    #[test]
+    fn test_instrumented_vfs() {
+        let ops = Arc::new(Mutex::new(Vec::new()));
+        let recorded = ops.clone();
+        register_vfs("test_instrumented_vfs", None, false, move |event| {
+                let op = match *event {
+                    VfsEvent::Open { .. } => Op::Open,
+                    VfsEvent::Read { .. } => Op::Read,
+                    VfsEvent::Write { .. } => Op::Write,
+                    VfsEvent::Sync { .. } => Op::Sync,
+                    _ => return true,
+                };
+                recorded.lock().unwrap().push(op);
+                true
+            })
+            .unwrap();
+        assert!(register_vfs("test_instrumented_vfs", None, false, |_| true).is_err());
+        assert!(register_vfs("test_instrumented_vfs_2", Some("no-such-vfs"), false, |_| true)
+                    .is_err());
+
+        let temp_dir = TempDir::new("test_instrumented_vfs").unwrap();
+        let path = temp_dir.path().join("test.db3");
+        {
+            let db = Connection::open_with_flags_and_vfs(&path,
+                                                         OpenFlags::default(),
+                                                         "test_instrumented_vfs")
+                .unwrap();
+            db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES (1);")
+                .unwrap();
+        }
+        {
+            let ops = ops.lock().unwrap();
+            assert_eq!(Some(&Op::Open), ops.first());
+            assert!(ops.contains(&Op::Write));
+            assert!(ops.contains(&Op::Sync));
+        }
+
+        // Connections using other VFSes are not instrumented.
+        ops.lock().unwrap().clear();
+        let db = Connection::open(&path).unwrap();
+        let x: i64 = db.query_row("SELECT x FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!(1, x);
+        assert!(ops.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_fault_injection() {
+        let fail_writes = Arc::new(AtomicBool::new(false));
+        let fail = fail_writes.clone();
+        register_vfs("test_fault_injection", None, false, move |event| {
+                match *event {
+                    VfsEvent::Write { .. } => !fail.load(Ordering::SeqCst),
+                    _ => true,
+                }
+            })
+            .unwrap();
+
+        let temp_dir = TempDir::new("test_fault_injection").unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = Connection::open_with_flags_and_vfs(&path,
+                                                     OpenFlags::default(),
+                                                     "test_fault_injection")
+            .unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+
+        fail_writes.store(true, Ordering::SeqCst);
+        match db.execute("INSERT INTO foo VALUES (1)", &[]).unwrap_err() {
+            Error::SqliteFailure(err, _) => {
+                assert_eq!(ErrorCode::SystemIOFailure, err.code);
+            }
+            err => panic!("Unexpected error {}", err),
+        }
+
+        fail_writes.store(false, Ordering::SeqCst);
+        db.execute("INSERT INTO foo VALUES (1)", &[]).unwrap();
+        let count: i64 = db.query_row("SELECT COUNT(*) FROM foo", &[], |r| r.get(0)).unwrap();
+        assert_eq!(1, count);
+    }
+}
@@ -9,7 +9,7 @@ extern crate rusqlite;
 #[cfg(feature = "trace")]
 fn main() {
     use std::os::raw::c_int;
-    use std::sync::Mutex;
+    use std::sync::{Arc, Mutex};
 
     lazy_static! {
         static ref LOGS_RECEIVED: Mutex<Vec<(c_int, String)>> = Mutex::new(Vec::new());
@@ -20,17 +20,49 @@ fn main() {
         logs_received.push((err, message.to_owned()));
     }
 
-    use rusqlite::trace;
+    use rusqlite::{trace, Connection};
 
     unsafe { trace::config_log(Some(log_handler)) }.unwrap();
     trace::log(10, "First message from rusqlite");
-    unsafe { trace::config_log(None) }.unwrap();
+    unsafe { trace::config_log(None::<fn(c_int, &str)>) }.unwrap();
     trace::log(11, "Second message from rusqlite");
 
-    let logs_received = LOGS_RECEIVED.lock().unwrap();
-    assert_eq!(logs_received.len(), 1);
-    assert_eq!(logs_received[0].0, 10);
-    assert_eq!(logs_received[0].1, "First message from rusqlite");
+    {
+        let logs_received = LOGS_RECEIVED.lock().unwrap();
+        assert_eq!(logs_received.len(), 1);
+        assert_eq!(logs_received[0].0, 10);
+        assert_eq!(logs_received[0].1, "First message from rusqlite");
+    }
+
+    // Closures can capture state; SQLite logs a warning when it creates an automatic index.
+    let notices = Arc::new(Mutex::new(Vec::new()));
+    let handler_notices = notices.clone();
+    unsafe {
+        trace::config_log(Some(move |err: c_int, message: &str| {
+            handler_notices.lock().unwrap().push((err, message.to_owned()));
+        }))
+    }
+        .unwrap();
+
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch("CREATE TABLE t1(a); CREATE TABLE t2(b);
+                      INSERT INTO t1 VALUES (1); INSERT INTO t2 VALUES (1);")
+        .unwrap();
+    let count: i64 = db.query_row("SELECT COUNT(*) FROM t1, t2 WHERE a = b", &[], |r| r.get(0))
+        .unwrap();
+    assert_eq!(count, 1);
+
+    {
+        // SQLITE_WARNING_AUTOINDEX
+        let notices = notices.lock().unwrap();
+        assert!(notices.iter().any(|&(err, ref message)| {
+                                       err == 284 && message.starts_with("automatic index on")
+                                   }),
+                "no automatic index notice in {:?}",
+                *notices);
+    }
+    // Configuring SQLite after it has been initialized fails.
+    assert!(unsafe { trace::config_log(None::<fn(c_int, &str)>) }.is_err());
 }
 
 #[cfg(not(feature = "trace"))]
@@ -0,0 +1,25 @@
+//! This file contains unit tests for `rusqlite::config`. These functions affect SQLite
+//! process-wide and must be called before SQLite is initialized, and so are not safe to run as
+//! a normal #[test] in the library.
+
+extern crate rusqlite;
+
+use rusqlite::{config, Connection, Error, ErrorCode};
+
+fn main() {
+    unsafe {
+        config::set_lookaside(512, 64).unwrap();
+        config::set_page_cache(4096 + 256, 16).unwrap();
+    }
+
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch("CREATE TABLE foo (x INTEGER); INSERT INTO foo VALUES (42)").unwrap();
+    let x: i64 = db.query_row("SELECT x FROM foo", &[], |r| r.get(0)).unwrap();
+    assert_eq!(42, x);
+
+    // SQLite is now initialized, so it can no longer be configured.
+    match unsafe { config::set_lookaside(256, 32) } {
+        Err(Error::SqliteFailure(err, _)) => assert_eq!(ErrorCode::APIMisuse, err.code),
+        r => panic!("Unexpected result {:?}", r),
+    }
+}